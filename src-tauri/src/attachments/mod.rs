@@ -0,0 +1,98 @@
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use sha2::{Digest, Sha256};
+use tauri::http::{header::CONTENT_TYPE, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::configuration::Configuration;
+
+pub const ATTACHMENT_SCHEME: &str = "attachment:";
+// Webviews load `attachment:<name>` references from this protocol, see `serve`.
+pub const URI_SCHEME: &str = "attachment";
+
+// Files referenced by blocks, such as images, copied into an `attachments` directory next to the
+// database. Files are named after their content hash, so storing the same file twice keeps one copy.
+#[derive(Debug)]
+pub struct AttachmentStore {
+    dir: PathBuf,
+}
+
+impl AttachmentStore {
+    pub fn for_configuration(configuration: &Configuration) -> Self {
+        let dir = match configuration.db_path.parent() {
+            Some(parent) => parent.join("attachments"),
+            None => PathBuf::from("attachments"),
+        };
+
+        AttachmentStore { dir }
+    }
+
+    // Copies `source` into the store and returns the reference to save on the block.
+    pub fn store_file(&self, source: &Path) -> Result<String, String> {
+        let bytes = std::fs::read(source).map_err(|e| {
+            log::error!("Could not read attachment {:?}: {:?}", source, e);
+            String::from("Could not read attachment")
+        })?;
+
+        let mut name = format!("{:x}", Sha256::digest(&bytes));
+        if let Some(extension) = source.extension() {
+            name = format!("{}.{}", name, extension.to_string_lossy().to_lowercase());
+        }
+
+        let destination = self.dir.join(&name);
+        if !destination.exists() {
+            log::debug!("Storing attachment {:?} as {:?}", source, &destination);
+
+            std::fs::create_dir_all(&self.dir).map_err(|e| {
+                log::error!("Could not create attachments directory {:?}: {:?}", &self.dir, e);
+                String::from("Could not create attachments directory")
+            })?;
+            std::fs::write(&destination, bytes).map_err(|e| {
+                log::error!("Could not write attachment {:?}: {:?}", &destination, e);
+                String::from("Could not write attachment")
+            })?;
+        }
+
+        Ok(format!("{}{}", ATTACHMENT_SCHEME, name))
+    }
+
+    pub fn path_for(&self, reference: &str) -> Option<PathBuf> {
+        let name = reference.strip_prefix(ATTACHMENT_SCHEME)?;
+
+        // Names are hashes, anything that could leave the directory is not a stored attachment.
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return None;
+        }
+
+        Some(self.dir.join(name))
+    }
+}
+
+fn content_type(name: &str) -> &'static str {
+    match Path::new(name).extension().and_then(|extension| extension.to_str()) {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+// Answers the webviews' requests for `attachment://localhost/<name>` with the file from the store
+// of the active configuration. The path is checked by `path_for`, anything else is not found.
+pub fn serve<R: Runtime>(app: &AppHandle<R>, request: Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let name = request.uri().path().trim_start_matches('/');
+    let store = AttachmentStore::for_configuration(&app.state::<RwLock<Configuration>>().read().unwrap());
+
+    let file = store
+        .path_for(&format!("{}{}", ATTACHMENT_SCHEME, name))
+        .map(|path| std::fs::read(path).map_err(|e| log::debug!("Could not read attachment {}: {:?}", name, e)));
+
+    match file {
+        Some(Ok(bytes)) => Response::builder().header(CONTENT_TYPE, content_type(name)).body(bytes).unwrap(),
+        _ => Response::builder().status(StatusCode::NOT_FOUND).body(Vec::new()).unwrap(),
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use rusqlite::backup::Backup;
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+
+use crate::configuration::{BackupSettings, Configuration};
+use crate::events::{emit_app_event, BACKUP_RESTORED_EVENT};
+use crate::storage::encryption::{apply_key, check_key};
+use crate::storage::{self, Database, Passphrase};
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const BACKUP_EXTENSION: &str = "sqlite";
+// How often the scheduler checks whether a snapshot is due, so interval changes apply without a restart.
+const SCHEDULER_TICK: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupFile {
+    pub file_name: String,
+    pub created_at_utc: DateTime<Utc>,
+    pub size: u64,
+}
+
+// Snapshots of the database, taken with SQLite's online backup API into a `backups` directory next to it.
+// Several databases can share a directory, so snapshots are named after their database.
+// Snapshots of an encrypted database are encrypted with its passphrase.
+#[derive(Debug)]
+pub struct BackupStore {
+    dir: PathBuf,
+    prefix: String,
+    passphrase: Option<Passphrase>,
+}
+
+impl BackupStore {
+    pub fn for_configuration(configuration: &Configuration) -> Self {
+        let dir = match configuration.db_path.parent() {
+            Some(parent) => parent.join("backups"),
+            None => PathBuf::from("backups"),
+        };
+        let prefix = match configuration.db_path.file_stem() {
+            Some(stem) => stem.to_string_lossy().to_string(),
+            None => String::from("db"),
+        };
+
+        BackupStore {
+            dir,
+            prefix,
+            passphrase: configuration.passphrase.clone(),
+        }
+    }
+
+    pub fn create(&self, connection: &Connection) -> Result<BackupFile, String> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| {
+            log::error!("Could not create backups directory {:?}: {:?}", &self.dir, e);
+            String::from("Could not create backups directory")
+        })?;
+
+        let created_at_utc = Utc::now();
+        let (file_name, path, partial_path) = self.reserve(created_at_utc)?;
+        log::debug!("Backing up database to {:?}", &path);
+
+        let backup_error = |e: rusqlite::Error| {
+            log::error!("Could not back up database to {:?}: {:?}", &partial_path, e);
+            String::from("Could not back up database")
+        };
+
+        let mut snapshot = Connection::open(&partial_path).map_err(backup_error)?;
+        if let Some(passphrase) = &self.passphrase {
+            apply_key(&snapshot, passphrase).map_err(backup_error)?;
+        }
+        copy_database(connection, &mut snapshot).map_err(backup_error)?;
+        // A snapshot is a single file, whatever journal mode the live database uses.
+        snapshot
+            .pragma_update_and_check(None, "journal_mode", "DELETE", |_| Ok(()))
+            .map_err(backup_error)?;
+        drop(snapshot);
+
+        std::fs::rename(&partial_path, &path).map_err(|e| {
+            log::error!("Could not move backup to {:?}: {:?}", &path, e);
+            String::from("Could not back up database")
+        })?;
+
+        let size = std::fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or_default();
+        log::info!("Backed up database to {:?}", &path);
+
+        Ok(BackupFile {
+            file_name,
+            created_at_utc,
+            size,
+        })
+    }
+
+    // Names carry the second a snapshot was taken, later snapshots within the same second get a sequence suffix.
+    // The snapshot is written under another name first, so one that failed halfway is never listed. Creating
+    // that file claims the name, two snapshots taken at once never share it.
+    fn reserve(&self, created_at_utc: DateTime<Utc>) -> Result<(String, PathBuf, PathBuf), String> {
+        let timestamp = created_at_utc.format(TIMESTAMP_FORMAT).to_string();
+
+        for sequence in 1.. {
+            let file_name = match sequence {
+                1 => format!("{}-{}.{}", self.prefix, timestamp, BACKUP_EXTENSION),
+                _ => format!("{}-{}-{}.{}", self.prefix, timestamp, sequence, BACKUP_EXTENSION),
+            };
+            let path = self.dir.join(&file_name);
+            let partial_path = path.with_extension("partial");
+            if path.exists() {
+                continue;
+            }
+
+            match std::fs::OpenOptions::new().write(true).create_new(true).open(&partial_path) {
+                Ok(_) => return Ok((file_name, path, partial_path)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => {
+                    log::error!("Could not create backup {:?}: {:?}", &partial_path, e);
+                    return Err(String::from("Could not back up database"));
+                }
+            }
+        }
+
+        unreachable!()
+    }
+
+    // Newest first.
+    pub fn list(&self) -> Result<Vec<BackupFile>, String> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let entries = std::fs::read_dir(&self.dir).map_err(|e| {
+            log::error!("Could not read backups directory {:?}: {:?}", &self.dir, e);
+            String::from("Could not read backups directory")
+        })?;
+
+        let mut backups: Vec<BackupFile> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let created_at_utc = self.created_at(&file_name)?;
+                let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or_default();
+
+                Some(BackupFile {
+                    file_name,
+                    created_at_utc,
+                    size,
+                })
+            })
+            .collect();
+        backups.sort_by_key(|backup| std::cmp::Reverse(self.parse_name(&backup.file_name)));
+
+        Ok(backups)
+    }
+
+    // Only names of this database's snapshots parse, anything else in the directory is ignored.
+    fn created_at(&self, file_name: &str) -> Option<DateTime<Utc>> {
+        self.parse_name(file_name).map(|(created_at, _)| created_at)
+    }
+
+    fn parse_name(&self, file_name: &str) -> Option<(DateTime<Utc>, u32)> {
+        let name = file_name
+            .strip_prefix(&self.prefix)?
+            .strip_prefix('-')?
+            .strip_suffix(BACKUP_EXTENSION)?
+            .strip_suffix('.')?;
+        let (timestamp, sequence) = match name.split_once('-') {
+            Some((timestamp, sequence)) if sequence.bytes().all(|byte| byte.is_ascii_digit()) => (timestamp, sequence.parse().ok()?),
+            Some(_) => return None,
+            None => (name, 1),
+        };
+
+        NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)
+            .ok()
+            .map(|created_at| (created_at.and_utc(), sequence))
+    }
+
+    pub fn path_for(&self, file_name: &str) -> Option<PathBuf> {
+        self.created_at(file_name)?;
+        if file_name.contains(['/', '\\']) {
+            return None;
+        }
+
+        Some(self.dir.join(file_name)).filter(|path| path.exists())
+    }
+
+    pub fn prune(&self, settings: &BackupSettings) -> Result<usize, String> {
+        let backups = self.list()?;
+        let keep = backups_to_keep(&backups, settings);
+
+        let mut removed = 0;
+        for backup in backups.iter().filter(|backup| !keep.contains(&backup.file_name)) {
+            match std::fs::remove_file(self.dir.join(&backup.file_name)) {
+                Ok(_) => removed += 1,
+                Err(e) => log::error!("Could not remove backup {}: {:?}", &backup.file_name, e),
+            }
+        }
+
+        log::debug!("Pruned {} backups", removed);
+        Ok(removed)
+    }
+}
+
+fn copy_database(source: &Connection, destination: &mut Connection) -> rusqlite::Result<()> {
+    Backup::new(source, destination)?.run_to_completion(100, Duration::ZERO, None)
+}
+
+// Keeps the newest snapshot of each of the last days and weeks that have one, and always the newest one.
+fn backups_to_keep(backups: &[BackupFile], settings: &BackupSettings) -> HashSet<String> {
+    let mut keep = HashSet::new();
+    let mut days = Vec::new();
+    let mut weeks = Vec::new();
+
+    for backup in backups {
+        let day = backup.created_at_utc.date_naive();
+        let week = backup.created_at_utc.iso_week();
+
+        if !days.contains(&day) && days.len() < settings.keep_daily {
+            days.push(day);
+            keep.insert(backup.file_name.clone());
+        }
+        if !weeks.contains(&week) && weeks.len() < settings.keep_weekly {
+            weeks.push(week);
+            keep.insert(backup.file_name.clone());
+        }
+    }
+
+    if let Some(newest) = backups.first() {
+        keep.insert(newest.file_name.clone());
+    }
+
+    keep
+}
+
+// Snapshots the database the app currently uses, then prunes according to the configured retention.
+pub fn backup_now(app: &AppHandle) -> Result<BackupFile, String> {
+    let configuration = app.state::<RwLock<Configuration>>().read().unwrap().clone();
+    let connection = app.state::<Database>().get().map_err(|_| String::from("Could not get db connection"))?;
+
+    let store = BackupStore::for_configuration(&configuration);
+    let backup = store.create(&connection)?;
+    store.prune(&configuration.backups)?;
+
+    Ok(backup)
+}
+
+pub fn start_scheduler(app: AppHandle) {
+    std::thread::spawn(move || {
+        let mut last_backup = Instant::now();
+
+        loop {
+            std::thread::sleep(SCHEDULER_TICK);
+
+            let interval_minutes = app.state::<RwLock<Configuration>>().read().unwrap().backups.interval_minutes;
+            if interval_minutes == 0 || last_backup.elapsed() < Duration::from_secs(interval_minutes * 60) {
+                continue;
+            }
+            if app.state::<Database>().is_locked() {
+                continue;
+            }
+
+            if let Err(e) = backup_now(&app) {
+                log::error!("Scheduled backup failed: {}", e);
+            }
+            last_backup = Instant::now();
+        }
+    });
+}
+
+pub fn backup_on_exit(app: &AppHandle) {
+    if !app.state::<RwLock<Configuration>>().read().unwrap().backups.on_exit || app.state::<Database>().is_locked() {
+        return;
+    }
+
+    if let Err(e) = backup_now(app) {
+        log::error!("Backup on exit failed: {}", e);
+    }
+}
+
+#[tauri::command]
+pub fn list_backups_command(configuration: State<RwLock<Configuration>>) -> Result<String, String> {
+    log::debug!("Running list_backups_command");
+
+    let store = BackupStore::for_configuration(&configuration.read().unwrap());
+
+    Ok(serde_json::to_string(&store.list()?).unwrap())
+}
+
+#[derive(Debug, Serialize)]
+pub struct RestoreSummary {
+    pub restored: String,
+    // Snapshot of the database as it was before the restore, to undo it.
+    pub previous: BackupFile,
+}
+
+#[tauri::command]
+pub fn restore_backup_command(
+    file_name: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running restore_backup_command for {}", file_name);
+    // Held for the whole restore, so no command pairs the restored database with another configuration.
+    let configuration = configuration.write().unwrap();
+
+    let store = BackupStore::for_configuration(&configuration);
+    let backup_path = store.path_for(&file_name).ok_or_else(|| String::from("Backup not found"))?;
+
+    // Snapshots taken before the passphrase was changed, or before encrypting, can not be restored with the current one.
+    check_key(&backup_path, store.passphrase.as_ref())
+        .map_err(|_| String::from("Backup was taken with a different passphrase"))?;
+    let backup_connection = Connection::open(&backup_path).map_err(|_| String::from("Could not open backup"))?;
+    if let Some(passphrase) = &store.passphrase {
+        apply_key(&backup_connection, passphrase).map_err(|_| String::from("Could not open backup"))?;
+    }
+    storage::check_version(&backup_connection)?;
+
+    let previous = store.create(&*db.get()?)?;
+
+    // Every connection is returned and closed before the file is overwritten, an open one would keep
+    // writing pages of the replaced database.
+    let _closed = db.close(Duration::from_millis(configuration.database.connection_timeout_ms))?;
+    let restored = storage::open_connection(&configuration).and_then(|mut connection| {
+        copy_database(&backup_connection, &mut connection).map_err(|e| {
+            log::error!("Could not restore {:?}: {:?}", &backup_path, e);
+            String::from("Could not restore backup")
+        })?;
+        // Snapshots taken by an older version are brought up to date.
+        storage::run_migrations(&mut connection)
+    });
+    db.replace(storage::setup_database(&configuration)?);
+    restored?;
+    log::info!("Restored database from {:?}", &backup_path);
+
+    emit_app_event(&app, BACKUP_RESTORED_EVENT, file_name.clone());
+
+    Ok(serde_json::to_string(&RestoreSummary {
+        restored: file_name,
+        previous,
+    })
+    .unwrap())
+}
@@ -0,0 +1,289 @@
+// Works on the same configuration and database as the app, without its windows. The database has to be
+// in WAL mode, so the app keeps reading while this writes, and its windows show the changes once they reload.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use log::LevelFilter;
+use rusqlite::Connection;
+use serde::Serialize;
+use uuid::Uuid;
+
+use my_blocks_lib::attachments::AttachmentStore;
+use my_blocks_lib::backups::BackupStore;
+use my_blocks_lib::blocks::repository::{self, BlockRepository};
+use my_blocks_lib::blocks::search::{pages_titled, search_blocks};
+use my_blocks_lib::blocks::{Block, BlockContent};
+use my_blocks_lib::configuration::{Arguments, Configuration, JournalMode};
+use my_blocks_lib::interchange::markdown_export::{export_page, page_files, page_markdown, page_title};
+use my_blocks_lib::interchange::markdown_import::import_markdown_file;
+use my_blocks_lib::interchange::opml::{import_opml_file, write_opml};
+use my_blocks_lib::interchange::workspace_json::import_workspace_file;
+use my_blocks_lib::storage;
+use my_blocks_lib::workspaces::activate_workspace;
+
+#[derive(Debug, Parser)]
+#[command(name = "my-blocks-cli", version, about = "Capture and read My Blocks notes from the terminal")]
+struct Cli {
+    #[command(flatten)]
+    arguments: Arguments,
+
+    /// Print JSON instead of text, for scripts
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Add a block at the end of a page
+    Add {
+        text: String,
+        /// Page id or title, the home page of the workspace when left out
+        #[arg(long)]
+        page: Option<String>,
+        /// Read the text as markdown, it is kept as typed otherwise
+        #[arg(long)]
+        markdown: bool,
+    },
+    /// List the blocks of a page
+    Ls {
+        /// Page id or title, the home page of the workspace when left out
+        page: Option<String>,
+    },
+    /// Find blocks containing some text
+    Search {
+        query: String,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Export a page, to the terminal or with the pages below it into a directory
+    Export {
+        /// Page id or title
+        page: String,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Md)]
+        format: ExportFormat,
+        /// Directory to write markdown files into, one per page
+        #[arg(long, value_name = "DIR")]
+        output: Option<PathBuf>,
+    },
+    /// Import a markdown or OPML file as a new page, or restore a workspace JSON export
+    Import {
+        file: PathBuf,
+        /// Page id or title to import below, the home page of the workspace when left out
+        #[arg(long)]
+        page: Option<String>,
+    },
+    /// Snapshot the database into its backups directory
+    Backup,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Md,
+    Opml,
+    Json,
+}
+
+fn main() -> ExitCode {
+    let mut cli = Cli::parse();
+    // Logs would mix with the output, --log-level turns them back on.
+    cli.arguments.log_level = cli.arguments.log_level.or(Some(LevelFilter::Off));
+
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), String> {
+    let mut configuration = Configuration::init(cli.arguments)?;
+    // WAL lets a running app keep reading while this writes. The configured journal mode is kept,
+    // opening the database in another one would convert it.
+    if configuration.database.journal_mode != JournalMode::Wal {
+        return Err(format!(
+            "The database is configured with the {} journal mode, the CLI needs WAL to run next to the app. Set journalMode = \"WAL\" in the [database] section of {:?}",
+            configuration.database.journal_mode.as_pragma(),
+            configuration.config_path
+        ));
+    }
+
+    // A single command runs at a time. Only the copy is changed, saving the configuration keeps the app's settings.
+    let mut database_configuration = configuration.clone();
+    database_configuration.database.pool_size = 1;
+
+    let pool = storage::setup_database(&database_configuration)?;
+    let mut connection = pool.get().map_err(|_| String::from("Could not get db connection"))?;
+    let output = Output { json: cli.json };
+
+    match cli.command {
+        Command::Add { text, page, markdown } => {
+            let repository = repository::for_connection(&connection);
+            let page = find_page(page.as_deref(), &configuration, &connection)?;
+
+            let mut block = Block::new(page.id, String::from("paragraph"), String::new());
+            block.block_contents = match markdown {
+                true => BlockContent::from_markdown(String::from("paragraph"), &text),
+                false => BlockContent::new(String::from("paragraph"), text),
+            };
+            block.block_order = Some(repository.insert(&block)?);
+
+            output.print(&block, || format!("Added {} to {}", block.id, page_title(&page)))
+        }
+        Command::Ls { page } => {
+            let page = find_page(page.as_deref(), &configuration, &connection)?;
+
+            output.print(&page, || {
+                let mut text = format!("{} ({})\n", page_title(&page), page.id);
+                write_tree(&page.children, 1, &mut text);
+                text
+            })
+        }
+        Command::Search { query, limit } => {
+            let results = search_blocks(&query, limit, &connection)?;
+
+            output.print(&results, || {
+                results
+                    .iter()
+                    .map(|result| match &result.page_title {
+                        Some(page_title) => format!("{}: {} ({})\n", page_title, result.text, result.id),
+                        None => format!("{} ({})\n", result.text, result.id),
+                    })
+                    .collect()
+            })
+        }
+        Command::Export { page, format: ExportFormat::Md, output: Some(dir) } => {
+            let page = find_page(Some(&page), &configuration, &connection)?;
+            let written_files = export_page(&page, &dir, &AttachmentStore::for_configuration(&configuration))?;
+
+            output.print(&written_files, || {
+                written_files.iter().map(|file| format!("{}\n", file.display())).collect()
+            })
+        }
+        Command::Export { output: Some(_), .. } => Err(String::from("--output only applies to markdown exports")),
+        Command::Export { page, format, output: None } => {
+            let page = find_page(Some(&page), &configuration, &connection)?;
+
+            // The export is the output, --json does not wrap it.
+            let export = match format {
+                ExportFormat::Md => {
+                    let page_files = page_files(&page, "md");
+                    let (_, file) = &page_files.pages[0];
+                    page_markdown(&page, file, &page_files.files)
+                }
+                ExportFormat::Opml => {
+                    let mut opml = Vec::new();
+                    write_opml(&page, &mut opml)?;
+                    String::from_utf8(opml).unwrap()
+                }
+                ExportFormat::Json => serde_json::to_string_pretty(&page).unwrap(),
+            };
+
+            write_output(&export)
+        }
+        Command::Import { file, page } => {
+            let extension = file
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+
+            if extension == "json" {
+                let summary = import_workspace_file(&file, &mut connection)?;
+                // A running app sees the configuration file change and switches to the restored workspace as well.
+                activate_workspace(summary.workspace_id, &mut configuration, &connection)?;
+                return output.print(&summary, || {
+                    format!("Restored {} blocks of workspace {}, it is now the active workspace", summary.blocks, summary.workspace_id)
+                });
+            }
+
+            let parent = find_page(page.as_deref(), &configuration, &connection)?;
+            let page = match extension.as_str() {
+                "md" | "markdown" => {
+                    let attachments = AttachmentStore::for_configuration(&configuration);
+                    import_markdown_file(&file, parent.id, &attachments, &mut connection)?
+                }
+                "opml" | "xml" => import_opml_file(&file, parent.id, &mut connection)?,
+                _ => return Err(format!("Can not import {}, use a .md, .opml or .json file", file.display())),
+            };
+
+            output.print(&page, || format!("Imported {} as {} ({})", file.display(), page_title(&page), page.id))
+        }
+        Command::Backup => {
+            let store = BackupStore::for_configuration(&configuration);
+            let backup = store.create(&connection)?;
+            store.prune(&configuration.backups)?;
+
+            output.print(&backup, || format!("Backed up to {}", backup.file_name))
+        }
+    }
+}
+
+struct Output {
+    json: bool,
+}
+
+impl Output {
+    fn print<T: Serialize>(&self, value: &T, text: impl FnOnce() -> String) -> Result<(), String> {
+        if self.json {
+            write_output(&serde_json::to_string(value).unwrap())
+        } else {
+            write_output(&text())
+        }
+    }
+}
+
+// A closed pipe, as when the output goes to `head`, ends the output rather than failing.
+fn write_output(output: &str) -> Result<(), String> {
+    match writeln!(std::io::stdout().lock(), "{}", output.trim_end()) {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        Err(e) => {
+            log::error!("Could not write output: {:?}", e);
+            Err(String::from("Could not write output"))
+        }
+    }
+}
+
+// A page id, or the title of a single page. Without either, the home page of the active workspace.
+fn find_page(page: Option<&str>, configuration: &Configuration, connection: &Connection) -> Result<Block, String> {
+    let repository = repository::for_connection(connection);
+
+    let page_id = match page {
+        None => return Block::find_or_create_home_page(configuration.workspace_id, &repository).map(|(page, _)| page),
+        Some(page) => match Uuid::parse_str(page) {
+            Ok(page_id) => page_id,
+            Err(_) => match pages_titled(page, connection)?.as_slice() {
+                [page_id] => *page_id,
+                [] => return Err(format!("No page is titled {}", page)),
+                _ => return Err(format!("Several pages are titled {}, use the page id", page)),
+            },
+        },
+    };
+
+    match repository.get(page_id)? {
+        Some(block) if block.block_type == "page" => Ok(block),
+        _ => Err(String::from("Page not found")),
+    }
+}
+
+// Nested pages are listed by title, their blocks are on pages of their own.
+fn write_tree(blocks: &[Block], depth: usize, text: &mut String) {
+    for block in blocks {
+        let indent = "  ".repeat(depth);
+        if block.block_type == "page" {
+            text.push_str(&format!("{}> {} ({})\n", indent, page_title(block), block.id));
+            continue;
+        }
+
+        let markdown = block.block_contents.to_markdown().replace('\n', &format!("\n{}  ", indent));
+        text.push_str(&format!("{}- {}\n", indent, markdown));
+        write_tree(&block.children, depth + 1, text);
+    }
+}
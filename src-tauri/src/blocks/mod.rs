@@ -1,20 +1,36 @@
-use r2d2_sqlite::SqliteConnectionManager;
 use serde::{Deserialize, Serialize};
-use rusqlite::{Connection, Result, Row, Statement, ToSql};
+use rusqlite::{Connection, Result, Row};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
-use tauri::State;
-use r2d2::Pool;
+use tauri::{AppHandle, State};
 
 use crate::configuration::Configuration;
+use crate::events::{emit_block_event, BlockEvent};
+use crate::storage::Database;
 
 mod block;
+pub mod repository;
+pub mod rich_text;
+pub mod search;
+#[cfg(test)]
+mod tests;
 
-#[derive(Debug, Deserialize, Serialize)]
+use repository::BlockRepository;
+use rich_text::RichText;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BlockContent {
     pub content_type: String,
     pub contents: Option<String>,
     pub title: Option<String>,
+    // Formatted text of text blocks. `contents` then holds its plain-text projection, used for search.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rich_text: Option<RichText>,
+    // Type specific attributes, e.g. the level of a heading or whether a todo is checked.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, serde_json::Value>,
 }
 
 impl BlockContent {
@@ -23,19 +39,81 @@ impl BlockContent {
     }
 
     pub fn from_json_string(json_string: &str) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(json_string)
+        let mut content: BlockContent = serde_json::from_str(json_string)?;
+
+        // Text blocks written before rich text existed only have plain `contents`.
+        if content.rich_text.is_none() && content.holds_rich_text() {
+            if let Some(contents) = &content.contents {
+                content.rich_text = Some(RichText::plain(contents));
+            }
+        }
+
+        Ok(content)
     }
 
+    // `raw_data` is literal text, `2*3*4` stays as typed. Use `from_markdown` for Markdown input.
     pub fn new (block_content_type: String,raw_data: String) -> Self {
-        BlockContent {
+        let mut content = BlockContent {
             content_type: block_content_type,
             contents: Some(raw_data),
             title: None,
+            rich_text: None,
+            properties: BTreeMap::new(),
+        };
+
+        if content.holds_rich_text() {
+            let rich_text = RichText::plain(content.contents.as_deref().unwrap_or_default());
+            content.set_rich_text(rich_text);
+        }
+
+        content
+    }
+
+    pub fn from_markdown(block_content_type: String, markdown: &str) -> Self {
+        let mut content = BlockContent::new(block_content_type, markdown.to_string());
+
+        if content.holds_rich_text() {
+            content.set_rich_text(RichText::from_markdown(markdown));
+        }
+
+        content
+    }
+
+    // Pages and workspaces use `contents` for their title and home page id, code blocks and images for
+    // their source and url, tables for their title. Everything else is formatted text.
+    pub fn holds_rich_text(&self) -> bool {
+        !matches!(self.content_type.as_str(), "page" | "workspace" | "code" | "image" | "table")
+    }
+
+    pub fn set_rich_text(&mut self, rich_text: RichText) {
+        self.contents = Some(rich_text.plain_text());
+        self.rich_text = Some(rich_text);
+    }
+
+    pub fn plain_text(&self) -> String {
+        match &self.rich_text {
+            Some(rich_text) => rich_text.plain_text(),
+            None => self.contents.clone().unwrap_or_default(),
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        match &self.rich_text {
+            Some(rich_text) => rich_text.to_markdown(),
+            None => self.contents.clone().unwrap_or_default(),
         }
     }
 }
 
-#[derive (Debug, Deserialize, Serialize)]
+pub(crate) fn malformed_column<E>(row: &Row, column: &str, e: E) -> rusqlite::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let index = row.as_ref().column_index(column).unwrap_or_default();
+    rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e))
+}
+
+#[derive (Debug, Clone, Deserialize, Serialize)]
 pub struct Block {
     pub id: Uuid,
     pub parent_id: Uuid,
@@ -75,115 +153,25 @@ impl Block {
         }
     }
 
+    // Shorthands for code that works on a connection directly, such as the importers.
     pub fn save(&self, connection: &Connection) -> Result<(), ()> {
-        log::debug!("Saving block {:?}", &self.id);
-
-        if self.is_stored() {
-            // TODO: Update block
-            log::debug!("Block already stored {:?}", &self.id);
-            return Ok(());
-        }
-
-        connection.execute(
-            "INSERT INTO blocks (id, parent_id, block_type, data, block_order, favorite, created_at_utc, updated_at_utc) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            rusqlite::params![
-                &self.id.to_string(), 
-                &self.parent_id.to_string(), 
-                &self.block_type, 
-                &self.block_contents.to_json().unwrap(),
-                match &self.load_last_block_in_same_page(connection).unwrap() {
-                    Some(block) => block.block_order.unwrap_or_default() + 1,
-                    None => 0,
-                },
-                &self.favorite,
-                &self.created_at_utc.to_rfc3339(), 
-                &self.updated_at_utc.to_rfc3339()],
-        ).unwrap();
-
-        Ok(())
-    }
-
-    fn load_last_block_in_same_page(
-        &self,
-        connection: &Connection,
-    ) -> Result<Option<Block>, ()> {
-        let mut statement: Statement;
-        let mut rows;
-        statement = connection.prepare("SELECT * FROM blocks WHERE parent_id = ?1 ORDER BY block_order DESC LIMIT 1").unwrap();
-        rows = statement.query_map(rusqlite::params![self.parent_id.to_string()], |row| {
-            Block::from_row(row, connection)
-        }).unwrap();
-
-        let mut blocks: Vec<Block> = Vec::new();
-        for row in rows {
-            blocks.push(row.unwrap());
-        }
-
-        return Ok(blocks.pop());
-    }
-
-    fn is_stored(&self) -> bool {
-        false
-    }
-
-    fn from_row(row: &Row, connection: &Connection) -> Result<Self> {
-        let uuid_string: String = row.get("id").unwrap();
-        let parent_uuid_string: String = row.get("parent_id").unwrap();
-        let created_at_string: String = row.get("created_at_utc").unwrap();
-        let updated_at_string: String = row.get("updated_at_utc").unwrap();
-        let block_data: String = row.get("data").unwrap();
-
-        Ok(Block {
-            id: Uuid::parse_str(&uuid_string).unwrap(),
-            parent_id: Uuid::parse_str(&parent_uuid_string).unwrap(),
-            block_type: row.get("block_type")?,
-            block_contents: BlockContent::from_json_string(&block_data).unwrap(),
-            block_order: row.get("block_order")?,
-            favorite: row.get("favorite")?,
-            children: Block::load_block_children_for_id(Uuid::parse_str(&uuid_string).unwrap(), connection).unwrap(),
-            created_at_utc: DateTime::<Utc>::from(DateTime::parse_from_rfc3339(&created_at_string).unwrap()),
-            updated_at_utc: DateTime::<Utc>::from(DateTime::parse_from_rfc3339(&&updated_at_string).unwrap())
-        })
+        repository::for_connection(connection).save(self).map_err(|_| ())
     }
 
-    fn load_block_children_for_id(uuid: Uuid, connection: &Connection) -> Result<Vec<Block>, ()> {
-        let mut statement: Statement;
-        let mut rows;
-
-        statement = connection.prepare("SELECT * FROM blocks WHERE parent_id = ?1 ORDER BY block_order ASC").unwrap();
-        rows = statement.query_map(rusqlite::params![uuid.to_string()], |row| {
-            Block::from_row(row, connection)
-        }).unwrap();
-
-        let mut blocks: Vec<Block> = Vec::new();
-        for row in rows {
-            blocks.push(row.unwrap());
-        }
-
-        Ok(blocks)
+    // Deletes the block and everything nested below it, returning how many rows were removed.
+    pub fn delete_with_descendants(id: Uuid, connection: &Connection) -> Result<usize, String> {
+        repository::for_connection(connection).delete(id)
     }
 
-    fn load_for_parent(
-        parent_id: Uuid,
+    pub(crate) fn load_by_id(
+        id: Uuid,
         connection: &Connection,
-    ) -> Result<Vec<Block>, ()> {
-        let mut statement: Statement;
-
-        statement = connection.prepare("SELECT * FROM blocks WHERE parent_id = ?1 ORDER BY block_order ASC").unwrap();
-        let rows = statement.query_map(rusqlite::params![parent_id.to_string()], |row| {
-            Block::from_row(row, connection)
-        }).unwrap();
-
-        let mut blocks: Vec<Block> = Vec::new();
-        for row in rows {
-            blocks.push(row.unwrap());
-        }
-
-        Ok(blocks)
+    ) -> Result<Option<Block>, ()> {
+        repository::for_connection(connection).get(id).map_err(|_| ())
     }
 
-    pub fn find_or_create_workspace_block(workspace_id: Uuid, connection: &Connection) -> Result<Self, String> {
-        match Block::load_by_id(workspace_id, connection).unwrap() {
+    pub fn find_or_create_workspace_block(workspace_id: Uuid, repository: &impl BlockRepository) -> Result<Self, String> {
+        match repository.get(workspace_id)? {
             Some(workspace_block) => return Ok(workspace_block),
             None => {
                 let mut workspace_block = Block {
@@ -210,69 +198,42 @@ impl Block {
                     updated_at_utc: Utc::now(),
                 };
 
-                homepage.save(connection).unwrap();
+                repository.insert(&homepage)?;
 
                 workspace_block.block_contents.contents.replace(String::from(&homepage.id.to_string()));
                 workspace_block.children.push(homepage);
-                workspace_block.save(connection).unwrap();
+                repository.insert(&workspace_block)?;
 
                 Ok(workspace_block)
             }
         }
     }
 
+    // The home page of the workspace, created when the workspace has none. Also returns whether it was created.
+    pub fn find_or_create_home_page(workspace_id: Uuid, repository: &impl BlockRepository) -> Result<(Self, bool), String> {
+        let mut workspace = Block::find_or_create_workspace_block(workspace_id, repository)?;
 
-    fn load_by_id(
-        id: Uuid,
-        connection: &Connection,
-    ) -> Result<Option<Block>, ()> {
-        let mut statement: Statement;
-        let mut rows;
-
-        statement = connection.prepare("SELECT * FROM blocks WHERE id = ?1").unwrap();
-        rows = statement.query_map(rusqlite::params![id.to_string()], |row| {
-            Block::from_row(row, connection)
-        }).unwrap();
-
-        let mut blocks: Vec<Block> = Vec::new();
-        for row in rows {
-            blocks.push(row.unwrap());
+        // The workspace block holds the id of its home page.
+        let home_page_id = workspace.block_contents.contents.as_deref().and_then(|id| Uuid::parse_str(id).ok());
+        if let Some(home_page) = home_page_id.map(|id| repository.get(id)).transpose()?.flatten() {
+            log::debug!("Homepage loaded.");
+            return Ok((home_page, false));
         }
 
-        Ok(blocks.pop())
-    }
-
-    fn change_block_order(&self, new_order: i32, connection: &Connection) -> Result<Self, ()> {
-        log::debug!("Moving block {} to position {}", self.id, new_order);
-
-
-        if (new_order > self.block_order.unwrap_or_default()) {
-            log::debug!("Moving other blocks up");
-            let old_order = self.block_order.unwrap_or_default();            connection.execute(
-                "UPDATE blocks SET block_order = (block_order - 1) WHERE parent_id = ?1 AND (block_order < ?2 OR block_order >= ?3)",
-                rusqlite::params![
-                    self.parent_id.to_string(),
-                    old_order,
-                    new_order
-                ]
-            ).unwrap();
-
-        } else {
-            log::debug!("Moving other blocks down");
-            connection.execute(
-                "UPDATE blocks SET block_order = block_order + 1 WHERE parent_id = ?1 AND block_order < ?2",
-                rusqlite::params![self.parent_id.to_string(), self.block_order.unwrap_or_default()],
-            ).unwrap();
+        if let Some(home_page) = repository.children(workspace_id)?.pop() {
+            log::debug!("Homepage loaded.");
+            return Ok((home_page, false));
         }
 
-        connection.execute(
-            "UPDATE blocks SET block_order = ?1 WHERE id = ?2",
-            rusqlite::params![new_order, &self.id.to_string()],
-        ).unwrap();
+        log::debug!("No home page block found, creating one.");
+        let mut home_page = Block::new(workspace_id, "page".to_string(), "Home".to_string());
+        home_page.block_order = Some(repository.insert(&home_page)?);
+
+        workspace.block_contents.contents.replace(home_page.id.to_string());
+        repository.update(&workspace)?;
 
-        Ok(Block::load_by_id(self.id, connection).unwrap().unwrap())
+        Ok((home_page, true))
     }
-
 }
 
 #[tauri::command]
@@ -280,13 +241,23 @@ pub fn create_block_command(
     raw_data: String,
     block_type: String,
     parent_id: String,
-    db: State<Pool<SqliteConnectionManager>>,
+    markdown: Option<bool>,
+    db: State<Database>,
+    app: AppHandle,
 ) -> Result<String, String> {
     log::debug!("Running create_block_command");
-    let connection = db.get().expect("Could not get db connection");
+    let connection = db.get()?;
+    let repository = repository::for_connection(&connection);
+
+    let mut block = Block::new(Uuid::parse_str(&parent_id).unwrap(), block_type, raw_data);
+    // Typed text is stored as it is, unless the frontend submits it as Markdown.
+    if markdown.unwrap_or_default() {
+        let markdown = block.block_contents.contents.clone().unwrap_or_default();
+        block.block_contents = BlockContent::from_markdown(block.block_contents.content_type.clone(), &markdown);
+    }
+    block.block_order = Some(repository.insert(&block)?);
 
-    let block = Block::new(Uuid::parse_str(&parent_id).unwrap(), block_type, raw_data);
-    block.save(&connection).unwrap();
+    emit_block_event(&app, BlockEvent::Created, vec![block.id], vec![block.parent_id]);
 
     Ok(serde_json::to_string(&block).unwrap())
 }
@@ -300,14 +271,15 @@ pub struct PageBlocksResponse {
 #[tauri::command]
 pub fn load_blocks_for_page_command(
     page_id: Option<String>,
-    db: State<Pool<SqliteConnectionManager>>,
+    db: State<Database>,
 ) -> Result<String, String> {
     log::debug!("Running load_blocks_for_page_command for page {:?}", page_id);
-    let connection = db.get().expect("Could not get db connection");
+    let connection = db.get()?;
+    let repository = repository::for_connection(&connection);
 
     let response = PageBlocksResponse {
         page_id: page_id.clone(),
-        blocks: Block::load_for_parent(Uuid::parse_str(&page_id.unwrap()).unwrap(), &connection).unwrap(),
+        blocks: repository.children(Uuid::parse_str(&page_id.unwrap()).unwrap())?,
     };
 
     Ok(serde_json::to_string(&response).unwrap())
@@ -316,12 +288,13 @@ pub fn load_blocks_for_page_command(
 #[tauri::command]
 pub fn get_block_command(
     block_id: String,
-    db: State<Pool<SqliteConnectionManager>>
+    db: State<Database>
 ) -> Result<String, String> {
     log::debug!("Running get_block_command for block {:?}", block_id);
-    let connection = db.get().expect("Could not get db connection");
+    let connection = db.get()?;
+    let repository = repository::for_connection(&connection);
 
-    let blocks = Block::load_by_id(Uuid::parse_str(&block_id).unwrap(), &connection).unwrap();
+    let blocks = repository.get(Uuid::parse_str(&block_id).unwrap())?;
 
     Ok(serde_json::to_string(&blocks).unwrap())
 }
@@ -330,41 +303,42 @@ pub fn get_block_command(
 pub fn change_block_order_command(
     block_id: String,
     new_order: i32,
-    db: State<Pool<SqliteConnectionManager>>,
+    db: State<Database>,
+    app: AppHandle,
 ) -> Result<String, String> {
     log::debug!("Running change_block_order_command for block {}", block_id);
-    let connection = db.get().expect("Could not get db connection");
+    let mut connection = db.get()?;
+    let transaction = connection.transaction().map_err(|_| String::from("Could not start transaction"))?;
 
     let uuid = Uuid::parse_str(&block_id).unwrap();
-    let block = Block::load_by_id(uuid, &connection).unwrap().unwrap();
-
-    let result = block.change_block_order(new_order, &connection);
+    let result = repository::for_connection(&transaction).reorder(uuid, new_order);
 
     match result {
-        Ok(block) => Ok(serde_json::to_string(&block).unwrap()),
+        Ok(block) => {
+            transaction.commit().map_err(|_| String::from("Could not change block order"))?;
+            emit_block_event(&app, BlockEvent::Moved, vec![block.id], vec![block.parent_id]);
+            Ok(serde_json::to_string(&block).unwrap())
+        },
         Err(_) => Err("Could not change block order".to_string()),
     }
 }
 
 #[tauri::command]
 pub fn load_home_page_command(
-    db: State<Pool<SqliteConnectionManager>>,
-    configuration: State<Configuration>
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
 ) -> Result<String, String> {
     log::debug!("Running load_home_page_command");
-    let connection = db.get().expect("Could not get db connection");
+    let connection = db.get()?;
+    let repository = repository::for_connection(&connection);
 
-    let mut block = Block::load_for_parent(configuration.workspace_id, &connection).unwrap();
+    let workspace_id = configuration.read().unwrap().workspace_id;
+    let (home_page, created) = Block::find_or_create_home_page(workspace_id, &repository)?;
 
-    if block.len() == 0 {
-        log::debug!("No home page block found, creating one.");
-
-        let new_block = Block::new(configuration.workspace_id, "page".to_string(), "Home".to_string());
-        new_block.save(&connection).unwrap();
-
-        return Ok(serde_json::to_string(&new_block).unwrap());
+    if created {
+        emit_block_event(&app, BlockEvent::Created, vec![home_page.id], vec![home_page.parent_id]);
     }
-    log::debug!("Homepage loaded.");
 
-    Ok(serde_json::to_string(&block.pop().unwrap()).unwrap())
-}
\ No newline at end of file
+    Ok(serde_json::to_string(&home_page).unwrap())
+}
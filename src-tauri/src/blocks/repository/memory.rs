@@ -0,0 +1,143 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::BlockRepository;
+use crate::blocks::Block;
+
+// Keeps blocks in memory only, for logic that should run without a database file.
+// Stored blocks have no children, they are filled in when a block is read.
+#[derive(Default)]
+pub struct InMemoryBlockRepository {
+    blocks: RefCell<BTreeMap<Uuid, Block>>,
+}
+
+impl InMemoryBlockRepository {
+    pub fn new() -> Self {
+        InMemoryBlockRepository::default()
+    }
+
+    fn with_children(&self, mut block: Block) -> Block {
+        block.children = self.children_of(block.id);
+        block
+    }
+
+    fn children_of(&self, parent_id: Uuid) -> Vec<Block> {
+        let mut children: Vec<Block> = self
+            .blocks
+            .borrow()
+            .values()
+            .filter(|block| block.parent_id == parent_id)
+            .cloned()
+            .collect();
+        children.sort_by_key(|block| block.block_order);
+
+        children.into_iter().map(|block| self.with_children(block)).collect()
+    }
+}
+
+impl BlockRepository for InMemoryBlockRepository {
+    fn get(&self, id: Uuid) -> Result<Option<Block>, String> {
+        let block = self.blocks.borrow().get(&id).cloned();
+
+        Ok(block.map(|block| self.with_children(block)))
+    }
+
+    fn get_without_children(&self, id: Uuid) -> Result<Option<Block>, String> {
+        Ok(self.blocks.borrow().get(&id).cloned())
+    }
+
+    fn children(&self, parent_id: Uuid) -> Result<Vec<Block>, String> {
+        Ok(self.children_of(parent_id))
+    }
+
+    fn contains(&self, id: Uuid) -> Result<bool, String> {
+        Ok(self.blocks.borrow().contains_key(&id))
+    }
+
+    fn position(&self, id: Uuid) -> Result<Option<(Uuid, i32)>, String> {
+        Ok(self
+            .blocks
+            .borrow()
+            .get(&id)
+            .map(|block| (block.parent_id, block.block_order.unwrap_or_default())))
+    }
+
+    fn next_order(&self, parent_id: Uuid) -> Result<i32, String> {
+        Ok(self
+            .blocks
+            .borrow()
+            .values()
+            .filter(|block| block.parent_id == parent_id)
+            .filter_map(|block| block.block_order)
+            .max()
+            .map_or(0, |order| order + 1))
+    }
+
+    fn insert_at(&self, block: &Block, block_order: i32) -> Result<(), String> {
+        if self.blocks.borrow().contains_key(&block.id) {
+            return Err(String::from("Block already stored"));
+        }
+
+        let mut stored = block.clone();
+        stored.block_order = Some(block_order);
+        stored.children = Vec::new();
+        self.blocks.borrow_mut().insert(stored.id, stored);
+
+        Ok(())
+    }
+
+    fn update_contents(&self, block: &Block) -> Result<(), String> {
+        let mut blocks = self.blocks.borrow_mut();
+        let stored = blocks.get_mut(&block.id).ok_or_else(|| String::from("Block not found"))?;
+
+        stored.block_type = block.block_type.clone();
+        stored.block_contents = block.block_contents.clone();
+        stored.favorite = block.favorite;
+        stored.updated_at_utc = Utc::now();
+
+        Ok(())
+    }
+
+    fn set_position(&self, id: Uuid, parent_id: Uuid, block_order: i32) -> Result<(), String> {
+        if let Some(block) = self.blocks.borrow_mut().get_mut(&id) {
+            block.parent_id = parent_id;
+            block.block_order = Some(block_order);
+        }
+
+        Ok(())
+    }
+
+    fn shift_orders(&self, parent_id: Uuid, orders: RangeInclusive<i32>, delta: i32) -> Result<(), String> {
+        for block in self.blocks.borrow_mut().values_mut() {
+            if block.parent_id == parent_id && block.block_order.is_some_and(|order| orders.contains(&order)) {
+                block.block_order = block.block_order.map(|order| order + delta);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete_tree(&self, id: Uuid) -> Result<usize, String> {
+        let mut tree = vec![id];
+        let mut index = 0;
+        while index < tree.len() {
+            let parent = tree[index];
+            let children: Vec<Uuid> = self
+                .blocks
+                .borrow()
+                .values()
+                .filter(|block| block.parent_id == parent && !tree.contains(&block.id))
+                .map(|block| block.id)
+                .collect();
+            tree.extend(children);
+            index += 1;
+        }
+
+        let mut blocks = self.blocks.borrow_mut();
+        Ok(tree.iter().filter(|id| blocks.remove(id).is_some()).count())
+    }
+}
@@ -0,0 +1,135 @@
+use std::ops::RangeInclusive;
+
+use rusqlite::Connection;
+use uuid::Uuid;
+
+use super::Block;
+
+#[cfg(test)]
+mod memory;
+mod sqlite;
+
+#[cfg(test)]
+pub use memory::InMemoryBlockRepository;
+
+// The repository commands work with, on a connection or a transaction of the database.
+pub fn for_connection(connection: &Connection) -> impl BlockRepository + '_ {
+    sqlite::SqliteBlockRepository::new(connection)
+}
+
+// Where blocks are stored. Siblings are ordered 0, 1, 2 and so on. Implementations only read and write
+// rows, the provided methods keep the orders that way whenever a block is added, removed or moved.
+pub trait BlockRepository {
+    // The block with everything nested below it.
+    fn get(&self, id: Uuid) -> Result<Option<Block>, String>;
+
+    // The block alone, its children are left empty.
+    fn get_without_children(&self, id: Uuid) -> Result<Option<Block>, String>;
+
+    // The children of a block in their order, each with everything nested below it.
+    fn children(&self, parent_id: Uuid) -> Result<Vec<Block>, String>;
+
+    fn contains(&self, id: Uuid) -> Result<bool, String>;
+
+    // The parent and order of a stored block.
+    fn position(&self, id: Uuid) -> Result<Option<(Uuid, i32)>, String>;
+
+    // The order after the last child of `parent_id`, 0 when it has none.
+    fn next_order(&self, parent_id: Uuid) -> Result<i32, String>;
+
+    // Stores a new block with the given order, without touching its siblings.
+    fn insert_at(&self, block: &Block, block_order: i32) -> Result<(), String>;
+
+    // Writes the block's type, contents and favorite flag.
+    fn update_contents(&self, block: &Block) -> Result<(), String>;
+
+    // Gives the block another parent and order, without touching its siblings.
+    fn set_position(&self, id: Uuid, parent_id: Uuid, block_order: i32) -> Result<(), String>;
+
+    // Adds `delta` to the order of the children of `parent_id` whose order is in `orders`.
+    fn shift_orders(&self, parent_id: Uuid, orders: RangeInclusive<i32>, delta: i32) -> Result<(), String>;
+
+    // Deletes the block and everything nested below it, returning how many blocks were removed.
+    fn delete_tree(&self, id: Uuid) -> Result<usize, String>;
+
+    fn load(&self, id: Uuid) -> Result<Block, String> {
+        self.get(id)?.ok_or_else(|| String::from("Block not found"))
+    }
+
+    fn stored_position(&self, id: Uuid) -> Result<(Uuid, i32), String> {
+        self.position(id)?.ok_or_else(|| String::from("Block not found"))
+    }
+
+    // Stores a new block after its last sibling, returning the order it was given.
+    fn insert(&self, block: &Block) -> Result<i32, String> {
+        log::debug!("Saving block {:?}", &block.id);
+        let block_order = self.next_order(block.parent_id)?;
+        self.insert_at(block, block_order)?;
+
+        Ok(block_order)
+    }
+
+    // Writes the block's type, contents and favorite flag. A different parent is refused, moving
+    // goes through `move_block` so the orders of both parents stay right.
+    fn update(&self, block: &Block) -> Result<(), String> {
+        let (parent_id, _) = self.stored_position(block.id)?;
+        if parent_id != block.parent_id {
+            return Err(String::from("Can not change the parent of a block when updating it, move it instead"));
+        }
+
+        self.update_contents(block)
+    }
+
+    fn delete(&self, id: Uuid) -> Result<usize, String> {
+        let (parent_id, block_order) = self.stored_position(id)?;
+
+        let deleted = self.delete_tree(id)?;
+        // Closes the gap the block left among its siblings.
+        self.shift_orders(parent_id, (block_order + 1)..=i32::MAX, -1)?;
+
+        Ok(deleted)
+    }
+
+    // Moves the block after the last child of another parent.
+    fn move_block(&self, id: Uuid, parent_id: Uuid) -> Result<Block, String> {
+        log::debug!("Moving block {} to parent {}", id, parent_id);
+        let (previous_parent_id, block_order) = self.stored_position(id)?;
+        if previous_parent_id == parent_id {
+            return self.load(id);
+        }
+
+        let new_order = self.next_order(parent_id)?;
+        self.set_position(id, parent_id, new_order)?;
+        self.shift_orders(previous_parent_id, (block_order + 1)..=i32::MAX, -1)?;
+
+        self.load(id)
+    }
+
+    // Moves the block to another position among its siblings, shifting the ones in between.
+    // Positions past the last sibling move it to the end.
+    fn reorder(&self, id: Uuid, new_order: i32) -> Result<Block, String> {
+        log::debug!("Moving block {} to position {}", id, new_order);
+        let (parent_id, old_order) = self.stored_position(id)?;
+        let new_order = new_order.clamp(0, self.next_order(parent_id)? - 1);
+
+        if new_order > old_order {
+            self.shift_orders(parent_id, (old_order + 1)..=new_order, -1)?;
+        } else if new_order < old_order {
+            self.shift_orders(parent_id, new_order..=(old_order - 1), 1)?;
+        }
+        self.set_position(id, parent_id, new_order)?;
+
+        self.load(id)
+    }
+
+    fn save(&self, block: &Block) -> Result<(), String> {
+        if self.contains(block.id)? {
+            log::debug!("Block already stored {:?}, updating it", &block.id);
+            // Moving first appends the block to its new parent and closes the gap in the old one.
+            self.move_block(block.id, block.parent_id)?;
+            return self.update(block);
+        }
+
+        self.insert(block).map(|_| ())
+    }
+}
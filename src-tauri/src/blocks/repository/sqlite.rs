@@ -0,0 +1,195 @@
+use std::ops::RangeInclusive;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Row};
+use uuid::Uuid;
+
+use super::BlockRepository;
+use crate::blocks::{malformed_column, Block, BlockContent};
+
+// Blocks in the `blocks` table. Works on a transaction as well, it derefs to a connection.
+pub struct SqliteBlockRepository<'a> {
+    connection: &'a Connection,
+}
+
+fn storage_error(e: rusqlite::Error) -> String {
+    log::error!("Could not access blocks: {:?}", e);
+    String::from("Could not access blocks")
+}
+
+fn block_data(block: &Block) -> Result<String, String> {
+    block.block_contents.to_json().map_err(|e| {
+        log::error!("Could not serialize block {}: {:?}", block.id, e);
+        String::from("Could not serialize block")
+    })
+}
+
+// A malformed row is an error rather than a panic, `check_integrity_command` reports which ones.
+fn block_without_children(row: &Row) -> rusqlite::Result<Block> {
+    let uuid_string: String = row.get("id")?;
+    let parent_uuid_string: String = row.get("parent_id")?;
+    let created_at_string: String = row.get("created_at_utc")?;
+    let updated_at_string: String = row.get("updated_at_utc")?;
+    let block_data: String = row.get("data")?;
+
+    let id = Uuid::parse_str(&uuid_string).map_err(|e| malformed_column(row, "id", e))?;
+
+    Ok(Block {
+        id,
+        parent_id: Uuid::parse_str(&parent_uuid_string).map_err(|e| malformed_column(row, "parent_id", e))?,
+        block_type: row.get("block_type")?,
+        block_contents: BlockContent::from_json_string(&block_data).map_err(|e| malformed_column(row, "data", e))?,
+        block_order: row.get("block_order")?,
+        favorite: row.get("favorite")?,
+        children: Vec::new(),
+        created_at_utc: DateTime::<Utc>::from(DateTime::parse_from_rfc3339(&created_at_string).map_err(|e| malformed_column(row, "created_at_utc", e))?),
+        updated_at_utc: DateTime::<Utc>::from(DateTime::parse_from_rfc3339(&updated_at_string).map_err(|e| malformed_column(row, "updated_at_utc", e))?)
+    })
+}
+
+impl<'a> SqliteBlockRepository<'a> {
+    pub fn new(connection: &'a Connection) -> Self {
+        SqliteBlockRepository { connection }
+    }
+
+    fn block_from_row(&self, row: &Row) -> rusqlite::Result<Block> {
+        let mut block = block_without_children(row)?;
+        block.children = self.load_blocks("SELECT * FROM blocks WHERE parent_id = ?1 ORDER BY block_order ASC", block.id)?;
+
+        Ok(block)
+    }
+
+    fn load_blocks(&self, query: &str, id: Uuid) -> rusqlite::Result<Vec<Block>> {
+        let mut statement = self.connection.prepare(query)?;
+        let rows = statement.query_map(rusqlite::params![id.to_string()], |row| self.block_from_row(row))?;
+
+        let mut blocks: Vec<Block> = Vec::new();
+        for row in rows {
+            match row {
+                Ok(block) => blocks.push(block),
+                Err(e) => log::error!("Skipping malformed block: {:?}", e),
+            }
+        }
+
+        Ok(blocks)
+    }
+}
+
+impl BlockRepository for SqliteBlockRepository<'_> {
+    fn get(&self, id: Uuid) -> Result<Option<Block>, String> {
+        let mut blocks = self.load_blocks("SELECT * FROM blocks WHERE id = ?1", id).map_err(storage_error)?;
+
+        Ok(blocks.pop())
+    }
+
+    fn get_without_children(&self, id: Uuid) -> Result<Option<Block>, String> {
+        self.connection
+            .query_row("SELECT * FROM blocks WHERE id = ?1", rusqlite::params![id.to_string()], block_without_children)
+            .optional()
+            .map_err(storage_error)
+    }
+
+    fn children(&self, parent_id: Uuid) -> Result<Vec<Block>, String> {
+        self.load_blocks("SELECT * FROM blocks WHERE parent_id = ?1 ORDER BY block_order ASC", parent_id)
+            .map_err(storage_error)
+    }
+
+    fn contains(&self, id: Uuid) -> Result<bool, String> {
+        let count: i64 = self
+            .connection
+            .query_row("SELECT COUNT(*) FROM blocks WHERE id = ?1", rusqlite::params![id.to_string()], |row| row.get(0))
+            .map_err(storage_error)?;
+
+        Ok(count > 0)
+    }
+
+    fn position(&self, id: Uuid) -> Result<Option<(Uuid, i32)>, String> {
+        let position: Option<(String, i32)> = self
+            .connection
+            .query_row(
+                "SELECT parent_id, block_order FROM blocks WHERE id = ?1",
+                rusqlite::params![id.to_string()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(storage_error)?;
+
+        position
+            .map(|(parent_id, block_order)| match Uuid::parse_str(&parent_id) {
+                Ok(parent_id) => Ok((parent_id, block_order)),
+                Err(_) => Err(String::from("Block has an invalid parent id")),
+            })
+            .transpose()
+    }
+
+    fn next_order(&self, parent_id: Uuid) -> Result<i32, String> {
+        self.connection
+            .query_row(
+                "SELECT COALESCE(MAX(block_order) + 1, 0) FROM blocks WHERE parent_id = ?1",
+                rusqlite::params![parent_id.to_string()],
+                |row| row.get(0),
+            )
+            .map_err(storage_error)
+    }
+
+    fn insert_at(&self, block: &Block, block_order: i32) -> Result<(), String> {
+        self.connection.execute(
+            "INSERT INTO blocks (id, parent_id, block_type, data, block_order, favorite, created_at_utc, updated_at_utc) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                &block.id.to_string(),
+                &block.parent_id.to_string(),
+                &block.block_type,
+                &block_data(block)?,
+                block_order,
+                &block.favorite,
+                &block.created_at_utc.to_rfc3339(),
+                &block.updated_at_utc.to_rfc3339()],
+        ).map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn update_contents(&self, block: &Block) -> Result<(), String> {
+        let updated = self.connection.execute(
+            "UPDATE blocks SET block_type = ?1, data = ?2, favorite = ?3, updated_at_utc = ?4 WHERE id = ?5",
+            rusqlite::params![
+                &block.block_type,
+                &block_data(block)?,
+                &block.favorite,
+                &Utc::now().to_rfc3339(),
+                &block.id.to_string()],
+        ).map_err(storage_error)?;
+
+        match updated {
+            0 => Err(String::from("Block not found")),
+            _ => Ok(()),
+        }
+    }
+
+    fn set_position(&self, id: Uuid, parent_id: Uuid, block_order: i32) -> Result<(), String> {
+        self.connection.execute(
+            "UPDATE blocks SET parent_id = ?1, block_order = ?2 WHERE id = ?3",
+            rusqlite::params![parent_id.to_string(), block_order, id.to_string()],
+        ).map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn shift_orders(&self, parent_id: Uuid, orders: RangeInclusive<i32>, delta: i32) -> Result<(), String> {
+        self.connection.execute(
+            "UPDATE blocks SET block_order = block_order + ?1 WHERE parent_id = ?2 AND block_order >= ?3 AND block_order <= ?4",
+            rusqlite::params![delta, parent_id.to_string(), orders.start(), orders.end()],
+        ).map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    // UNION rather than UNION ALL, so a loop of parents in a damaged database ends the recursion.
+    fn delete_tree(&self, id: Uuid) -> Result<usize, String> {
+        self.connection.execute(
+            "WITH RECURSIVE tree(id) AS (SELECT ?1 UNION SELECT blocks.id FROM blocks JOIN tree ON blocks.parent_id = tree.id)
+            DELETE FROM blocks WHERE id IN tree",
+            rusqlite::params![id.to_string()],
+        ).map_err(storage_error)
+    }
+}
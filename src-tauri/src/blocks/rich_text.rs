@@ -0,0 +1,554 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const PAGE_REFERENCE_SCHEME: &str = "page:";
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Span {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub bold: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub italic: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub code: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub strikethrough: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_reference: Option<Uuid>,
+}
+
+impl Span {
+    pub fn plain(text: &str) -> Self {
+        Span {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn same_format(&self, other: &Span) -> bool {
+        self.bold == other.bold
+            && self.italic == other.italic
+            && self.code == other.code
+            && self.strikethrough == other.strikethrough
+            && self.link == other.link
+            && self.page_reference == other.page_reference
+    }
+
+    fn same_target(&self, other: &Span) -> bool {
+        self.link == other.link && self.page_reference == other.page_reference
+    }
+
+    fn has_emphasis(&self) -> bool {
+        self.bold || self.italic || self.strikethrough
+    }
+
+    fn href(&self) -> Option<String> {
+        match (&self.page_reference, &self.link) {
+            (Some(page_id), _) => Some(format!("{}{}", PAGE_REFERENCE_SCHEME, page_id)),
+            (None, Some(link)) => Some(link.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+// Inline formatted text of a block, stored as a flat list of spans each carrying its own marks.
+//
+// The Markdown conversion is lossless for normalized rich text: `from_markdown(&text.to_markdown())`
+// gives back `text.normalized()`. Normalizing merges neighbouring spans with the same format, drops
+// empty spans and moves leading/trailing whitespace out of bold, italic and strikethrough marks,
+// which Markdown cannot express.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct RichText {
+    pub spans: Vec<Span>,
+}
+
+impl RichText {
+    pub fn plain(text: &str) -> Self {
+        RichText {
+            spans: vec![Span::plain(text)],
+        }
+        .normalized()
+    }
+
+    pub fn from_spans(spans: Vec<Span>) -> Self {
+        RichText { spans }.normalized()
+    }
+
+    pub fn plain_text(&self) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    pub fn from_markdown(markdown: &str) -> Self {
+        let chars: Vec<char> = markdown.chars().collect();
+        RichText::from_spans(parse_inline(&chars))
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let spans = self.normalized().spans;
+        let mut markdown = String::new();
+        let mut start = 0;
+
+        while start < spans.len() {
+            let mut end = start + 1;
+            while end < spans.len() && spans[end].same_target(&spans[start]) {
+                end += 1;
+            }
+
+            match spans[start].href() {
+                Some(href) => {
+                    markdown.push('[');
+                    write_emphasis_run(&spans[start..end], &mut markdown);
+                    markdown.push_str("](");
+                    markdown.push_str(&escape_href(&href));
+                    markdown.push(')');
+                }
+                None => write_emphasis_run(&spans[start..end], &mut markdown),
+            }
+
+            start = end;
+        }
+
+        markdown
+    }
+
+    pub fn normalized(&self) -> Self {
+        let mut split: Vec<Span> = Vec::new();
+
+        for span in &self.spans {
+            let mut span = span.clone();
+            if span.page_reference.is_some() {
+                span.link = None;
+            }
+            if span.text.is_empty() {
+                continue;
+            }
+            if span.code || !span.has_emphasis() {
+                split.push(span);
+                continue;
+            }
+
+            let trimmed_start = span.text.trim_start();
+            let leading = &span.text[..span.text.len() - trimmed_start.len()];
+            let trimmed = trimmed_start.trim_end();
+            let trailing = &trimmed_start[trimmed.len()..];
+
+            for (text, keeps_emphasis) in [(leading, false), (trimmed, true), (trailing, false)] {
+                if text.is_empty() {
+                    continue;
+                }
+                let mut part = span.clone();
+                part.text = text.to_string();
+                if !keeps_emphasis {
+                    part.bold = false;
+                    part.italic = false;
+                    part.strikethrough = false;
+                }
+                split.push(part);
+            }
+        }
+
+        let mut spans: Vec<Span> = Vec::new();
+        for span in split {
+            match spans.last_mut() {
+                Some(last) if last.same_format(&span) => last.text.push_str(&span.text),
+                _ => spans.push(span),
+            }
+        }
+
+        RichText { spans }
+    }
+}
+
+fn write_emphasis_run(spans: &[Span], markdown: &mut String) {
+    let mut bold = false;
+    let mut italic = false;
+    let mut strikethrough = false;
+
+    for span in spans {
+        write_toggles(markdown, &mut bold, &mut italic, &mut strikethrough, span);
+
+        if span.code {
+            markdown.push_str(&code_span(&span.text));
+        } else {
+            markdown.push_str(&escape_text(&span.text));
+        }
+    }
+
+    write_toggles(markdown, &mut bold, &mut italic, &mut strikethrough, &Span::default());
+}
+
+fn write_toggles(markdown: &mut String, bold: &mut bool, italic: &mut bool, strikethrough: &mut bool, span: &Span) {
+    if *strikethrough != span.strikethrough {
+        markdown.push_str("~~");
+        *strikethrough = span.strikethrough;
+    }
+    if *bold != span.bold {
+        markdown.push_str("**");
+        *bold = span.bold;
+    }
+    if *italic != span.italic {
+        markdown.push('*');
+        *italic = span.italic;
+    }
+}
+
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '~' | '`' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_href(href: &str) -> String {
+    let mut escaped = String::with_capacity(href.len());
+    for c in href.chars() {
+        if matches!(c, '\\' | '(' | ')' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    if href.chars().any(char::is_whitespace) {
+        return format!("<{}>", escaped);
+    }
+    escaped
+}
+
+fn code_span(text: &str) -> String {
+    let mut longest_run = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        if c == '`' {
+            run += 1;
+            longest_run = longest_run.max(run);
+        } else {
+            run = 0;
+        }
+    }
+
+    let fence = "`".repeat(longest_run + 1);
+    let needs_padding = text.starts_with('`')
+        || text.ends_with('`')
+        || (text.starts_with(' ') && text.ends_with(' ') && !text.chars().all(|c| c == ' '));
+
+    if needs_padding {
+        format!("{} {} {}", fence, text, fence)
+    } else {
+        format!("{}{}{}", fence, text, fence)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DelimiterKind {
+    StarBold,
+    StarItalic,
+    UnderscoreBold,
+    UnderscoreItalic,
+    Strikethrough,
+}
+
+impl DelimiterKind {
+    const ALL: [DelimiterKind; 5] = [
+        DelimiterKind::StarBold,
+        DelimiterKind::StarItalic,
+        DelimiterKind::UnderscoreBold,
+        DelimiterKind::UnderscoreItalic,
+        DelimiterKind::Strikethrough,
+    ];
+
+    fn literal(&self) -> &'static str {
+        match self {
+            DelimiterKind::StarBold => "**",
+            DelimiterKind::StarItalic => "*",
+            DelimiterKind::UnderscoreBold => "__",
+            DelimiterKind::UnderscoreItalic => "_",
+            DelimiterKind::Strikethrough => "~~",
+        }
+    }
+
+    fn index(&self) -> usize {
+        DelimiterKind::ALL.iter().position(|kind| kind == self).unwrap()
+    }
+}
+
+#[derive(Debug)]
+enum Item {
+    Text(String),
+    Code(String),
+    Link(Vec<Span>, String),
+    Delimiter {
+        kind: DelimiterKind,
+        can_open: bool,
+        can_close: bool,
+        toggles: bool,
+    },
+}
+
+// Returns the code span content and the index right after its closing backticks.
+fn find_code_span(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let fence_length = chars[start..].iter().take_while(|c| **c == '`').count();
+    let mut i = start + fence_length;
+
+    while i < chars.len() {
+        if chars[i] != '`' {
+            i += 1;
+            continue;
+        }
+
+        let run = chars[i..].iter().take_while(|c| **c == '`').count();
+        if run == fence_length {
+            let mut content: String = chars[start + fence_length..i].iter().collect();
+            if content.len() >= 2 && content.starts_with(' ') && content.ends_with(' ') && !content.chars().all(|c| c == ' ') {
+                content = content[1..content.len() - 1].to_string();
+            }
+            return Some((content, i + run));
+        }
+        i += run;
+    }
+
+    None
+}
+
+// Returns the link text, the unescaped href and the index right after the closing parenthesis.
+fn find_link(chars: &[char], start: usize) -> Option<(Vec<char>, String, usize)> {
+    let mut depth = 0;
+    let mut i = start + 1;
+
+    let text_end = loop {
+        match chars.get(i)? {
+            '\\' => i += 2,
+            '`' => match find_code_span(chars, i) {
+                Some((_, after)) => i = after,
+                None => i += chars[i..].iter().take_while(|c| **c == '`').count(),
+            },
+            '[' => {
+                depth += 1;
+                i += 1;
+            }
+            ']' if depth == 0 => break i,
+            ']' => {
+                depth -= 1;
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    };
+
+    if chars.get(text_end + 1) != Some(&'(') {
+        return None;
+    }
+
+    let mut i = text_end + 2;
+    let angled = chars.get(i) == Some(&'<');
+    if angled {
+        i += 1;
+    }
+
+    let mut href = String::new();
+    let mut parentheses = 0;
+    loop {
+        match chars.get(i)? {
+            '\\' => {
+                href.push(*chars.get(i + 1)?);
+                i += 2;
+            }
+            '>' if angled => {
+                if chars.get(i + 1) != Some(&')') {
+                    return None;
+                }
+                i += 1;
+                break;
+            }
+            '(' if !angled => {
+                parentheses += 1;
+                href.push('(');
+                i += 1;
+            }
+            ')' if !angled && parentheses == 0 => break,
+            ')' if !angled => {
+                parentheses -= 1;
+                href.push(')');
+                i += 1;
+            }
+            c if !angled && c.is_whitespace() => return None,
+            c => {
+                href.push(*c);
+                i += 1;
+            }
+        }
+    }
+
+    Some((chars[start + 1..text_end].to_vec(), href, i + 1))
+}
+
+fn push_text(items: &mut Vec<Item>, text: &str) {
+    match items.last_mut() {
+        Some(Item::Text(last)) => last.push_str(text),
+        _ => items.push(Item::Text(text.to_string())),
+    }
+}
+
+fn tokenize(chars: &[char]) -> Vec<Item> {
+    let mut items: Vec<Item> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                push_text(&mut items, &chars[i + 1].to_string());
+                i += 2;
+            }
+            '`' => match find_code_span(chars, i) {
+                Some((content, after)) => {
+                    items.push(Item::Code(content));
+                    i = after;
+                }
+                None => {
+                    let run = chars[i..].iter().take_while(|c| **c == '`').count();
+                    push_text(&mut items, &"`".repeat(run));
+                    i += run;
+                }
+            },
+            '[' => match find_link(chars, i) {
+                Some((text, href, after)) => {
+                    items.push(Item::Link(parse_inline(&text), href));
+                    i = after;
+                }
+                None => {
+                    push_text(&mut items, "[");
+                    i += 1;
+                }
+            },
+            c @ ('*' | '_' | '~') => {
+                let run = chars[i..].iter().take_while(|next| **next == c).count();
+                let previous = if i > 0 { chars.get(i - 1) } else { None };
+                let next = chars.get(i + run);
+
+                let left_flanking = next.is_some_and(|next| !next.is_whitespace());
+                let right_flanking = previous.is_some_and(|previous| !previous.is_whitespace());
+                let (can_open, can_close) = if c == '_' {
+                    (
+                        left_flanking && !previous.is_some_and(|p| p.is_alphanumeric()),
+                        right_flanking && !next.is_some_and(|n| n.is_alphanumeric()),
+                    )
+                } else {
+                    (left_flanking, right_flanking)
+                };
+
+                let kinds: &[DelimiterKind] = match (c, run) {
+                    ('*', 1) => &[DelimiterKind::StarItalic],
+                    ('*', 2) => &[DelimiterKind::StarBold],
+                    ('*', 3) => &[DelimiterKind::StarBold, DelimiterKind::StarItalic],
+                    ('_', 1) => &[DelimiterKind::UnderscoreItalic],
+                    ('_', 2) => &[DelimiterKind::UnderscoreBold],
+                    ('_', 3) => &[DelimiterKind::UnderscoreBold, DelimiterKind::UnderscoreItalic],
+                    ('~', 2) => &[DelimiterKind::Strikethrough],
+                    _ => &[],
+                };
+
+                if kinds.is_empty() {
+                    push_text(&mut items, &c.to_string().repeat(run));
+                }
+                for kind in kinds {
+                    items.push(Item::Delimiter {
+                        kind: *kind,
+                        can_open,
+                        can_close,
+                        toggles: false,
+                    });
+                }
+                i += run;
+            }
+            c => {
+                push_text(&mut items, &c.to_string());
+                i += 1;
+            }
+        }
+    }
+
+    items
+}
+
+// Pairs delimiters of the same kind; whatever stays unpaired is rendered literally.
+fn pair_delimiters(items: &mut [Item]) {
+    let mut openers: [Option<usize>; 5] = [None; 5];
+
+    for index in 0..items.len() {
+        let (kind, can_open, can_close) = match &items[index] {
+            Item::Delimiter { kind, can_open, can_close, .. } => (*kind, *can_open, *can_close),
+            _ => continue,
+        };
+
+        match openers[kind.index()] {
+            Some(opener) if can_close => {
+                for paired in [opener, index] {
+                    if let Item::Delimiter { toggles, .. } = &mut items[paired] {
+                        *toggles = true;
+                    }
+                }
+                openers[kind.index()] = None;
+            }
+            _ if can_open => openers[kind.index()] = Some(index),
+            _ => {}
+        }
+    }
+}
+
+fn parse_inline(chars: &[char]) -> Vec<Span> {
+    let mut items = tokenize(chars);
+    pair_delimiters(&mut items);
+
+    let mut active = [false; 5];
+    let mut spans: Vec<Span> = Vec::new();
+
+    let marked = |text: String, active: &[bool; 5]| Span {
+        text,
+        bold: active[DelimiterKind::StarBold.index()] || active[DelimiterKind::UnderscoreBold.index()],
+        italic: active[DelimiterKind::StarItalic.index()] || active[DelimiterKind::UnderscoreItalic.index()],
+        strikethrough: active[DelimiterKind::Strikethrough.index()],
+        ..Default::default()
+    };
+
+    for item in items {
+        match item {
+            Item::Text(text) => spans.push(marked(text, &active)),
+            Item::Code(text) => spans.push(Span {
+                code: true,
+                ..marked(text, &active)
+            }),
+            Item::Delimiter { kind, toggles: true, .. } => active[kind.index()] = !active[kind.index()],
+            Item::Delimiter { kind, .. } => spans.push(marked(kind.literal().to_string(), &active)),
+            Item::Link(inner, href) => {
+                let outer = marked(String::new(), &active);
+                let page_reference = href
+                    .strip_prefix(PAGE_REFERENCE_SCHEME)
+                    .and_then(|page_id| Uuid::parse_str(page_id).ok());
+
+                for span in inner {
+                    spans.push(Span {
+                        bold: span.bold || outer.bold,
+                        italic: span.italic || outer.italic,
+                        strikethrough: span.strikethrough || outer.strikethrough,
+                        link: if page_reference.is_some() { None } else { Some(href.clone()) },
+                        page_reference,
+                        ..span
+                    });
+                }
+            }
+        }
+    }
+
+    spans
+}
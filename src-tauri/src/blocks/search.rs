@@ -0,0 +1,99 @@
+use rusqlite::Connection;
+use serde::Serialize;
+use uuid::Uuid;
+
+// Pages nested in a cycle would otherwise be followed forever.
+const MAX_NESTING: i64 = 1000;
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub id: Uuid,
+    pub block_type: String,
+    // Plain text of the block, the title of a page.
+    pub text: String,
+    // The page the block is on, none for blocks outside any page.
+    pub page_id: Option<Uuid>,
+    pub page_title: Option<String>,
+}
+
+fn search_error(e: rusqlite::Error) -> String {
+    log::error!("Could not search blocks: {:?}", e);
+    String::from("Could not search blocks")
+}
+
+// Blocks whose plain text contains `query`, ignoring ASCII case, most recently updated first.
+pub fn search_blocks(query: &str, limit: usize, connection: &Connection) -> Result<Vec<SearchResult>, String> {
+    let pattern = format!("%{}%", query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+
+    let mut statement = connection
+        .prepare(
+            "SELECT id, block_type, json_extract(data, '$.contents') AS contents FROM blocks
+            WHERE block_type != 'workspace' AND contents LIKE ?1 ESCAPE '\\'
+            ORDER BY updated_at_utc DESC LIMIT ?2",
+        )
+        .map_err(search_error)?;
+    let rows = statement
+        .query_map(rusqlite::params![pattern, limit as i64], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?))
+        })
+        .map_err(search_error)?;
+
+    let mut results = Vec::new();
+    for row in rows {
+        let (id, block_type, text) = row.map_err(search_error)?;
+        let Ok(id) = Uuid::parse_str(&id) else {
+            continue;
+        };
+        let page = containing_page(id, connection).map_err(search_error)?;
+
+        results.push(SearchResult {
+            id,
+            block_type,
+            text: text.unwrap_or_default(),
+            page_id: page.as_ref().map(|(page_id, _)| *page_id),
+            page_title: page.and_then(|(_, title)| title),
+        });
+    }
+
+    Ok(results)
+}
+
+// The closest page above the block.
+fn containing_page(id: Uuid, connection: &Connection) -> rusqlite::Result<Option<(Uuid, Option<String>)>> {
+    let page = connection.query_row(
+        "WITH RECURSIVE ancestors(id, parent_id, block_type, data, depth) AS (
+            SELECT parent.id, parent.parent_id, parent.block_type, parent.data, 0
+            FROM blocks AS block JOIN blocks AS parent ON parent.id = block.parent_id WHERE block.id = ?1
+            UNION ALL
+            SELECT blocks.id, blocks.parent_id, blocks.block_type, blocks.data, ancestors.depth + 1
+            FROM blocks JOIN ancestors ON blocks.id = ancestors.parent_id
+            WHERE ancestors.block_type != 'page' AND ancestors.depth < ?2
+        )
+        SELECT id, json_extract(data, '$.contents') FROM ancestors WHERE block_type = 'page' ORDER BY depth LIMIT 1",
+        rusqlite::params![id.to_string(), MAX_NESTING],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)),
+    );
+
+    match page {
+        Ok((page_id, title)) => Ok(Uuid::parse_str(&page_id).ok().map(|page_id| (page_id, title))),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Pages titled `title`, ignoring ASCII case, oldest first.
+pub fn pages_titled(title: &str, connection: &Connection) -> Result<Vec<Uuid>, String> {
+    let mut statement = connection
+        .prepare(
+            "SELECT id FROM blocks WHERE block_type = 'page' AND json_extract(data, '$.contents') = ?1 COLLATE NOCASE
+            ORDER BY created_at_utc ASC",
+        )
+        .map_err(search_error)?;
+    let ids = statement
+        .query_map([title], |row| row.get::<_, String>(0))
+        .map_err(search_error)?
+        .collect::<rusqlite::Result<Vec<String>>>()
+        .map_err(search_error)?;
+
+    Ok(ids.iter().filter_map(|id| Uuid::parse_str(id).ok()).collect())
+}
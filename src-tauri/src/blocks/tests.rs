@@ -0,0 +1,431 @@
+use proptest::prelude::*;
+use uuid::Uuid;
+
+use super::repository::{self, BlockRepository, InMemoryBlockRepository};
+use super::rich_text::{RichText, Span};
+use super::search::{pages_titled, search_blocks};
+use super::{Block, BlockContent};
+use crate::storage::testing::in_memory_pool;
+
+// Each check runs against the SQLite repository and against the in-memory one.
+macro_rules! repository_tests {
+    ($($name:ident),* $(,)?) => {
+        mod sqlite {
+            use super::*;
+
+            $(
+                #[test]
+                fn $name() {
+                    let pool = in_memory_pool();
+                    let connection = pool.get().unwrap();
+                    super::$name(&repository::for_connection(&connection));
+                }
+            )*
+        }
+
+        mod in_memory {
+            use super::*;
+
+            $(
+                #[test]
+                fn $name() {
+                    super::$name(&InMemoryBlockRepository::new());
+                }
+            )*
+        }
+    };
+}
+
+repository_tests!(
+    save_appends_after_the_last_sibling,
+    save_updates_a_stored_block_in_place,
+    update_refuses_another_parent,
+    reorder_moves_a_block_down,
+    reorder_moves_a_block_up,
+    reorder_keeps_positions_within_the_siblings,
+    delete_closes_the_gap,
+    move_appends_to_the_new_parent,
+    find_or_create_workspace_block_is_idempotent,
+    children_are_loaded_in_order,
+    get_without_children_reads_the_block_alone,
+);
+
+fn contents(blocks: &[Block]) -> Vec<String> {
+    blocks
+        .iter()
+        .map(|block| block.block_contents.contents.clone().unwrap_or_default())
+        .collect()
+}
+
+fn orders(blocks: &[Block]) -> Vec<i32> {
+    blocks.iter().map(|block| block.block_order.unwrap()).collect()
+}
+
+// A page in a new workspace with the blocks "0", "1" and so on.
+fn page_with_blocks(repository: &impl BlockRepository, count: usize) -> (Uuid, Vec<Uuid>) {
+    let workspace = Block::find_or_create_workspace_block(Uuid::now_v7(), repository).unwrap();
+    let page = Block::new(workspace.children[0].id, String::from("page"), String::from("Page"));
+    repository.insert(&page).unwrap();
+
+    let ids = (0..count)
+        .map(|index| {
+            let block = Block::new(page.id, String::from("paragraph"), index.to_string());
+            repository.insert(&block).unwrap();
+            block.id
+        })
+        .collect();
+
+    (page.id, ids)
+}
+
+fn save_appends_after_the_last_sibling(repository: &impl BlockRepository) {
+    let (page_id, _) = page_with_blocks(repository, 3);
+
+    let block = Block::new(page_id, String::from("paragraph"), String::from("last"));
+    repository.save(&block).unwrap();
+
+    let children = repository.children(page_id).unwrap();
+    assert_eq!(orders(&children), vec![0, 1, 2, 3]);
+    assert_eq!(contents(&children), vec!["0", "1", "2", "last"]);
+}
+
+fn save_updates_a_stored_block_in_place(repository: &impl BlockRepository) {
+    let (page_id, ids) = page_with_blocks(repository, 3);
+
+    let mut block = repository.get(ids[1]).unwrap().unwrap();
+    block.block_contents.contents = Some(String::from("changed"));
+    repository.save(&block).unwrap();
+
+    let children = repository.children(page_id).unwrap();
+    assert_eq!(orders(&children), vec![0, 1, 2]);
+    assert_eq!(contents(&children), vec!["0", "changed", "2"]);
+}
+
+fn update_refuses_another_parent(repository: &impl BlockRepository) {
+    let (page_id, ids) = page_with_blocks(repository, 3);
+
+    let mut block = repository.get(ids[0]).unwrap().unwrap();
+    block.parent_id = ids[1];
+    assert!(repository.update(&block).is_err());
+
+    let children = repository.children(page_id).unwrap();
+    assert_eq!(orders(&children), vec![0, 1, 2]);
+    assert!(repository.children(ids[1]).unwrap().is_empty());
+}
+
+fn reorder_moves_a_block_down(repository: &impl BlockRepository) {
+    let (page_id, ids) = page_with_blocks(repository, 5);
+
+    let block = repository.reorder(ids[1], 3).unwrap();
+
+    assert_eq!(block.block_order, Some(3));
+    let children = repository.children(page_id).unwrap();
+    assert_eq!(orders(&children), vec![0, 1, 2, 3, 4]);
+    assert_eq!(contents(&children), vec!["0", "2", "3", "1", "4"]);
+}
+
+fn reorder_moves_a_block_up(repository: &impl BlockRepository) {
+    let (page_id, ids) = page_with_blocks(repository, 5);
+
+    let block = repository.reorder(ids[3], 1).unwrap();
+
+    assert_eq!(block.block_order, Some(1));
+    let children = repository.children(page_id).unwrap();
+    assert_eq!(orders(&children), vec![0, 1, 2, 3, 4]);
+    assert_eq!(contents(&children), vec!["0", "3", "1", "2", "4"]);
+}
+
+fn reorder_keeps_positions_within_the_siblings(repository: &impl BlockRepository) {
+    let (page_id, ids) = page_with_blocks(repository, 3);
+
+    repository.reorder(ids[0], 10).unwrap();
+    assert_eq!(contents(&repository.children(page_id).unwrap()), vec!["1", "2", "0"]);
+
+    repository.reorder(ids[0], -1).unwrap();
+    assert_eq!(contents(&repository.children(page_id).unwrap()), vec!["0", "1", "2"]);
+
+    repository.reorder(ids[1], 1).unwrap();
+    let children = repository.children(page_id).unwrap();
+    assert_eq!(orders(&children), vec![0, 1, 2]);
+    assert_eq!(contents(&children), vec!["0", "1", "2"]);
+}
+
+fn delete_closes_the_gap(repository: &impl BlockRepository) {
+    let (page_id, ids) = page_with_blocks(repository, 4);
+    repository.insert(&Block::new(ids[1], String::from("paragraph"), String::from("nested"))).unwrap();
+
+    assert_eq!(repository.delete(ids[1]).unwrap(), 2);
+
+    let children = repository.children(page_id).unwrap();
+    assert_eq!(orders(&children), vec![0, 1, 2]);
+    assert_eq!(contents(&children), vec!["0", "2", "3"]);
+}
+
+fn move_appends_to_the_new_parent(repository: &impl BlockRepository) {
+    let (page_id, ids) = page_with_blocks(repository, 3);
+    let (other_page_id, _) = page_with_blocks(repository, 2);
+
+    let block = repository.move_block(ids[0], other_page_id).unwrap();
+
+    assert_eq!(block.parent_id, other_page_id);
+    let children = repository.children(page_id).unwrap();
+    assert_eq!(orders(&children), vec![0, 1]);
+    assert_eq!(contents(&children), vec!["1", "2"]);
+    let other_children = repository.children(other_page_id).unwrap();
+    assert_eq!(orders(&other_children), vec![0, 1, 2]);
+    assert_eq!(contents(&other_children), vec!["0", "1", "0"]);
+}
+
+fn find_or_create_workspace_block_is_idempotent(repository: &impl BlockRepository) {
+    let workspace_id = Uuid::now_v7();
+
+    let created = Block::find_or_create_workspace_block(workspace_id, repository).unwrap();
+    let found = Block::find_or_create_workspace_block(workspace_id, repository).unwrap();
+
+    assert_eq!(created.id, workspace_id);
+    assert_eq!(found.id, workspace_id);
+    assert_eq!(found.block_type, "workspace");
+    // A single home page, which the workspace points to.
+    assert_eq!(found.children.len(), 1);
+    assert_eq!(found.block_contents.contents, Some(found.children[0].id.to_string()));
+    assert_eq!(created.children[0].id, found.children[0].id);
+
+    let (home_page, created_home_page) = Block::find_or_create_home_page(workspace_id, repository).unwrap();
+    assert_eq!(home_page.id, found.children[0].id);
+    assert!(!created_home_page);
+}
+
+fn children_are_loaded_in_order(repository: &impl BlockRepository) {
+    let (page_id, ids) = page_with_blocks(repository, 4);
+    repository.insert(&Block::new(ids[2], String::from("paragraph"), String::from("nested"))).unwrap();
+    repository.reorder(ids[3], 0).unwrap();
+    repository.reorder(ids[1], 3).unwrap();
+
+    let children = repository.children(page_id).unwrap();
+
+    assert_eq!(orders(&children), vec![0, 1, 2, 3]);
+    assert_eq!(contents(&children), vec!["3", "0", "2", "1"]);
+    assert_eq!(contents(&children[2].children), vec!["nested"]);
+    assert_eq!(contents(&repository.get(page_id).unwrap().unwrap().children), contents(&children));
+}
+
+fn get_without_children_reads_the_block_alone(repository: &impl BlockRepository) {
+    let (page_id, _) = page_with_blocks(repository, 2);
+
+    let page = repository.get_without_children(page_id).unwrap().unwrap();
+
+    assert_eq!(page.block_contents.title, repository.load(page_id).unwrap().block_contents.title);
+    assert!(page.children.is_empty());
+    assert_eq!(repository.get_without_children(Uuid::now_v7()).unwrap().map(|block| block.id), None);
+}
+
+#[test]
+fn block_save_assigns_orders_on_a_connection() {
+    let pool = in_memory_pool();
+    let connection = pool.get().unwrap();
+    let parent_id = Uuid::now_v7();
+
+    let blocks: Vec<Block> = (0..3)
+        .map(|index| Block::new(parent_id, String::from("paragraph"), index.to_string()))
+        .collect();
+    for block in &blocks {
+        block.save(&connection).unwrap();
+    }
+    // Saving again updates the block, it keeps its position.
+    blocks[0].save(&connection).unwrap();
+
+    let children = repository::for_connection(&connection).children(parent_id).unwrap();
+    assert_eq!(orders(&children), vec![0, 1, 2]);
+    assert_eq!(contents(&children), vec!["0", "1", "2"]);
+}
+
+#[test]
+fn search_finds_blocks_with_their_page() {
+    let pool = in_memory_pool();
+    let connection = pool.get().unwrap();
+    let repository = repository::for_connection(&connection);
+    let (page_id, ids) = page_with_blocks(&repository, 2);
+    let nested = Block::new(ids[0], String::from("paragraph"), String::from("100% Nested"));
+    repository.insert(&nested).unwrap();
+
+    let results = search_blocks("nested", 20, &connection).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, nested.id);
+    assert_eq!(results[0].page_id, Some(page_id));
+    assert_eq!(results[0].page_title.as_deref(), Some("Page"));
+
+    // Wildcards in the query match themselves only.
+    assert_eq!(search_blocks("100%", 20, &connection).unwrap().len(), 1);
+    assert!(search_blocks("1_0", 20, &connection).unwrap().is_empty());
+}
+
+#[test]
+fn pages_titled_ignores_case() {
+    let pool = in_memory_pool();
+    let connection = pool.get().unwrap();
+    let repository = repository::for_connection(&connection);
+    let (page_id, _) = page_with_blocks(&repository, 1);
+
+    assert_eq!(pages_titled("page", &connection).unwrap(), vec![page_id]);
+    assert!(pages_titled("Other", &connection).unwrap().is_empty());
+}
+
+// Markdown read into rich text and written back gives the same Markdown.
+fn assert_markdown_round_trip(markdown: &str) {
+    let rich_text = RichText::from_markdown(markdown);
+    assert_eq!(rich_text.to_markdown(), markdown);
+    assert_eq!(RichText::from_markdown(&rich_text.to_markdown()), rich_text);
+}
+
+#[test]
+fn emphasis_round_trips_through_markdown() {
+    let rich_text = RichText::from_markdown("plain **bold** *italic* ~~struck~~ `code`");
+
+    assert_eq!(
+        rich_text.spans,
+        vec![
+            Span::plain("plain "),
+            Span { bold: true, ..Span::plain("bold") },
+            Span::plain(" "),
+            Span { italic: true, ..Span::plain("italic") },
+            Span::plain(" "),
+            Span { strikethrough: true, ..Span::plain("struck") },
+            Span::plain(" "),
+            Span { code: true, ..Span::plain("code") },
+        ]
+    );
+    assert_eq!(rich_text.plain_text(), "plain bold italic struck code");
+
+    assert_markdown_round_trip("plain **bold** *italic* ~~struck~~ `code`");
+    assert_markdown_round_trip("***both*** and **bold** ***nested***");
+}
+
+#[test]
+fn links_round_trip_through_markdown() {
+    let rich_text = RichText::from_markdown("see [the **docs**](https://example.com/a_(b)) now");
+
+    assert_eq!(rich_text.spans[1].link.as_deref(), Some("https://example.com/a_(b)"));
+    assert!(rich_text.spans[2].bold);
+    assert_eq!(rich_text.spans[2].link.as_deref(), Some("https://example.com/a_(b)"));
+    assert_eq!(rich_text.plain_text(), "see the docs now");
+
+    assert_markdown_round_trip("see [the **docs**](https://example.com/a\\(b\\)) now");
+    assert_markdown_round_trip("[spaced](<https://example.com/a b>)");
+}
+
+#[test]
+fn page_references_round_trip_through_markdown() {
+    let page_id = Uuid::now_v7();
+    let markdown = format!("go to [Page](page:{})", page_id);
+
+    let rich_text = RichText::from_markdown(&markdown);
+
+    assert_eq!(rich_text.spans[1].page_reference, Some(page_id));
+    assert_eq!(rich_text.spans[1].link, None);
+    assert_markdown_round_trip(&markdown);
+}
+
+#[test]
+fn escaped_characters_round_trip_through_markdown() {
+    let literal = "2*3*4 _id_ [x] `tick` ~~ a\\b";
+    let rich_text = RichText::plain(literal);
+
+    let markdown = rich_text.to_markdown();
+    assert_eq!(RichText::from_markdown(&markdown), rich_text);
+    assert_eq!(RichText::from_markdown(&markdown).plain_text(), literal);
+
+    assert_markdown_round_trip("2\\*3\\*4 \\_id\\_");
+}
+
+#[test]
+fn typed_text_is_kept_as_it_is() {
+    let content = BlockContent::new(String::from("paragraph"), String::from("2*3*4 and _id_"));
+    assert_eq!(content.contents.as_deref(), Some("2*3*4 and _id_"));
+    assert_eq!(content.rich_text, Some(RichText::plain("2*3*4 and _id_")));
+
+    let content = BlockContent::from_markdown(String::from("paragraph"), "**bold**");
+    assert_eq!(content.contents.as_deref(), Some("bold"));
+    assert!(content.rich_text.unwrap().spans[0].bold);
+}
+
+// The block and every block nested below it.
+fn tree_ids(block: &Block) -> Vec<Uuid> {
+    std::iter::once(block.id).chain(block.children.iter().flat_map(tree_ids)).collect()
+}
+
+// The children of every parent are ordered 0, 1, 2 and so on.
+fn assert_contiguous(repository: &impl BlockRepository, parent_ids: &[Uuid]) -> Result<(), TestCaseError> {
+    for parent_id in parent_ids {
+        let children = repository.children(*parent_id).unwrap();
+        prop_assert_eq!(orders(&children), (0..children.len() as i32).collect::<Vec<i32>>());
+    }
+
+    Ok(())
+}
+
+proptest! {
+    // Applies the same random inserts, reorders, deletes, moves and re-parenting saves to both repositories.
+    #[test]
+    fn sibling_orders_stay_a_contiguous_permutation(operations in prop::collection::vec((0u8..5, 0usize..32, -2i32..12), 1..40)) {
+        let pool = in_memory_pool();
+        let connection = pool.get().unwrap();
+        let sqlite = repository::for_connection(&connection);
+        let in_memory = InMemoryBlockRepository::new();
+
+        let root_id = Uuid::now_v7();
+        let pages: Vec<Block> = (0..2).map(|index| Block::new(root_id, String::from("page"), index.to_string())).collect();
+        for page in &pages {
+            sqlite.insert(page).unwrap();
+            in_memory.insert(page).unwrap();
+        }
+        let page_ids: Vec<Uuid> = pages.iter().map(|page| page.id).collect();
+        let mut block_ids: Vec<Uuid> = Vec::new();
+
+        for (operation, index, order) in operations {
+            match operation {
+                0 => {
+                    // Below a page or nested in another block.
+                    let parents: Vec<Uuid> = page_ids.iter().chain(block_ids.iter()).copied().collect();
+                    let block = Block::new(parents[index % parents.len()], String::from("paragraph"), index.to_string());
+                    prop_assert_eq!(sqlite.insert(&block).unwrap(), in_memory.insert(&block).unwrap());
+                    block_ids.push(block.id);
+                }
+                1 if !block_ids.is_empty() => {
+                    let id = block_ids[index % block_ids.len()];
+                    prop_assert_eq!(sqlite.reorder(id, order).unwrap().block_order, in_memory.reorder(id, order).unwrap().block_order);
+                }
+                2 if !block_ids.is_empty() => {
+                    let id = block_ids[index % block_ids.len()];
+                    prop_assert_eq!(sqlite.delete(id).unwrap(), in_memory.delete(id).unwrap());
+                    block_ids.retain(|id| in_memory.contains(*id).unwrap());
+                }
+                3 if !block_ids.is_empty() => {
+                    let id = block_ids[index % block_ids.len()];
+                    let page_id = page_ids[order.unsigned_abs() as usize % page_ids.len()];
+                    prop_assert_eq!(sqlite.move_block(id, page_id).unwrap().block_order, in_memory.move_block(id, page_id).unwrap().block_order);
+                }
+                4 if !block_ids.is_empty() => {
+                    // Saves the block below another page or block, never below itself.
+                    let mut block = in_memory.load(block_ids[index % block_ids.len()]).unwrap();
+                    let below = tree_ids(&block);
+                    let parents: Vec<Uuid> = page_ids.iter().chain(block_ids.iter()).filter(|id| !below.contains(id)).copied().collect();
+                    block.parent_id = parents[order.unsigned_abs() as usize % parents.len()];
+                    block.block_contents.contents = Some(String::from("saved"));
+
+                    sqlite.save(&block).unwrap();
+                    in_memory.save(&block).unwrap();
+                    prop_assert_eq!(sqlite.stored_position(block.id).unwrap(), in_memory.stored_position(block.id).unwrap());
+                    prop_assert_eq!(sqlite.stored_position(block.id).unwrap().0, block.parent_id);
+                }
+                _ => {}
+            }
+
+            let parent_ids: Vec<Uuid> = page_ids.iter().chain(block_ids.iter()).copied().collect();
+            assert_contiguous(&sqlite, &parent_ids)?;
+            assert_contiguous(&in_memory, &parent_ids)?;
+            for parent_id in &parent_ids {
+                prop_assert_eq!(contents(&sqlite.children(*parent_id).unwrap()), contents(&in_memory.children(*parent_id).unwrap()));
+            }
+        }
+    }
+}
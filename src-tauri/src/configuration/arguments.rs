@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use log::LevelFilter;
+use uuid::Uuid;
+
+use crate::storage::Passphrase;
+
+// Every flag can also be set with its MY_BLOCKS_* environment variable, the flag wins when both are given.
+#[derive(Debug, Clone, Default, Parser)]
+#[command(name = "my-blocks", version, about = "A block based note taking app")]
+pub struct Arguments {
+    /// Configuration file to load and save instead of the default one
+    #[arg(long = "config", env = "MY_BLOCKS_CONFIG", value_name = "FILE")]
+    pub config_path: Option<PathBuf>,
+
+    /// Database file to open instead of the configured one
+    #[arg(long = "db", env = "MY_BLOCKS_DB", value_name = "FILE")]
+    pub db_path: Option<PathBuf>,
+
+    /// Workspace to open instead of the configured one
+    #[arg(long, env = "MY_BLOCKS_WORKSPACE", value_name = "ID")]
+    pub workspace: Option<Uuid>,
+
+    /// Run database migrations, development builds skip them otherwise
+    #[arg(long, env = "MY_BLOCKS_RUN_MIGRATIONS")]
+    pub run_migrations: bool,
+
+    /// Most verbose log level to print (off, error, warn, info, debug, trace)
+    #[arg(long, env = "MY_BLOCKS_LOG_LEVEL", value_name = "LEVEL", value_parser = parse_log_level)]
+    pub log_level: Option<LevelFilter>,
+
+    /// Passphrase of an encrypted database, prefer the environment variable as flags show up in process lists
+    #[arg(long, env = "MY_BLOCKS_PASSPHRASE", hide_env_values = true, value_name = "PASSPHRASE")]
+    pub passphrase: Option<Passphrase>,
+
+    /// Keep the configuration and database next to the executable
+    #[arg(long, env = "MY_BLOCKS_PORTABLE")]
+    pub portable: bool,
+}
+
+fn parse_log_level(value: &str) -> Result<LevelFilter, String> {
+    value.parse().map_err(|_| format!("Unknown log level {}", value))
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+// When `backups` snapshots the database and which snapshots it keeps.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BackupSettings {
+    // Minutes between snapshots while the app runs, 0 turns scheduled snapshots off.
+    #[serde(rename = "intervalMinutes")]
+    pub interval_minutes: u64,
+    #[serde(rename = "onExit")]
+    pub on_exit: bool,
+    // The newest snapshot of each of the last `keepDaily` days is kept, and of each of the last `keepWeekly` weeks.
+    #[serde(rename = "keepDaily")]
+    pub keep_daily: usize,
+    #[serde(rename = "keepWeekly")]
+    pub keep_weekly: usize,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings {
+            interval_minutes: 60,
+            on_exit: true,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+impl JournalMode {
+    pub fn as_pragma(&self) -> &'static str {
+        match self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+            JournalMode::Off => "OFF",
+        }
+    }
+}
+
+impl Synchronous {
+    pub fn as_pragma(&self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+// How `storage::setup_database` builds the pool and sets up each of its connections.
+// Changes apply the next time a database is opened.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DatabaseSettings {
+    #[serde(rename = "poolSize")]
+    pub pool_size: u32,
+    // How long a command waits for a free connection.
+    #[serde(rename = "connectionTimeoutMs")]
+    pub connection_timeout_ms: u64,
+    // How long a connection waits for another one to release its lock before failing with "database is locked".
+    #[serde(rename = "busyTimeoutMs")]
+    pub busy_timeout_ms: u64,
+    #[serde(rename = "journalMode")]
+    pub journal_mode: JournalMode,
+    pub synchronous: Synchronous,
+    #[serde(rename = "foreignKeys")]
+    pub foreign_keys: bool,
+}
+
+impl Default for DatabaseSettings {
+    // WAL lets readers work while a command writes, NORMAL is safe with WAL and much faster than FULL.
+    fn default() -> Self {
+        DatabaseSettings {
+            pool_size: 8,
+            connection_timeout_ms: 30_000,
+            busy_timeout_ms: 5_000,
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            foreign_keys: true,
+        }
+    }
+}
+
+impl DatabaseSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pool_size == 0 {
+            return Err(String::from("Database pool size must be at least 1"));
+        }
+        if self.connection_timeout_ms == 0 {
+            return Err(String::from("Database connection timeout must be more than 0"));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use std::io::Write;
+
+use chrono::Local;
+use env_logger::Builder;
+use log::LevelFilter;
+
+// Dev builds log file and line of every record at debug level, release builds log info and up.
+// A log level from the arguments replaces either default, raising it as well as lowering it.
+pub fn init(dev_mode: bool, log_level: Option<LevelFilter>) {
+    let mut builder = Builder::new();
+
+    if dev_mode {
+        builder.format(|buf, record| {
+            writeln!(
+                buf,
+                "{} [{}] - {}:{} - {}",
+                Local::now().format("%Y-%m-%dT%H:%M:%S"),
+                record.level(),
+                record.file().unwrap_or_default(),
+                record.line().unwrap_or_default(),
+                record.args()
+            )
+        });
+    } else {
+        builder.format(|buf, record| writeln!(buf, "{} - {}", Local::now().format("%Y-%m-%dT%H:%M:%S"), record.args()));
+    }
+
+    let default_level = if dev_mode { LevelFilter::Debug } else { LevelFilter::Info };
+    builder.filter(None, log_level.unwrap_or(default_level)).init();
+}
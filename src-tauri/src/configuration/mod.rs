@@ -1,15 +1,40 @@
+use rusqlite::{Connection, OpenFlags};
 use serde::{Deserialize, Serialize};
-use tauri::State;
+use tauri::{AppHandle, State};
 use uuid::Uuid;
 use std::borrow::Borrow;
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 use toml;
-use plogger;
 
-#[derive(Debug, Deserialize, Serialize)]
+mod arguments;
+mod backups;
+mod database;
+mod logging;
+mod preferences;
+mod upgrade;
+mod watcher;
+
+pub use arguments::Arguments;
+pub use backups::BackupSettings;
+pub use database::{DatabaseSettings, JournalMode};
+pub use preferences::Preferences;
+pub use watcher::watch_configuration;
+
+use crate::events::{emit_app_event, CONFIGURATION_CHANGED_EVENT};
+use crate::storage::{Database, Passphrase};
+use crate::workspaces::load_workspaces;
+
+const RECENT_DATABASES_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Configuration {
+    #[serde(default = "current_version")]
     pub version: String,
+    // Layout of this file, see `upgrade`.
+    #[serde(rename = "schemaVersion", default)]
+    pub schema_version: u32,
     #[serde(rename = "developmentMode")]
     pub development_mode: bool,
     #[serde(rename = "configurationPath")]
@@ -17,30 +42,83 @@ pub struct Configuration {
     #[serde(rename = "dbPath")]
     pub db_path: PathBuf,
     #[serde(rename="workspaceId")]
-    pub workspace_id: Uuid
+    pub workspace_id: Uuid,
+    // Databases opened with `open_database_command`, most recent first.
+    #[serde(rename = "recentDatabases", default)]
+    pub recent_databases: Vec<PathBuf>,
+    #[serde(default)]
+    pub preferences: Preferences,
+    #[serde(default)]
+    pub database: DatabaseSettings,
+    #[serde(default)]
+    pub backups: BackupSettings,
+    // Set with --run-migrations, only read at startup.
+    #[serde(skip)]
+    pub run_migrations: bool,
+    #[serde(skip)]
+    overrides: Overrides,
+    // Key of an encrypted database, never written to the file.
+    #[serde(skip)]
+    pub passphrase: Option<Passphrase>,
+}
+
+// Values taken from the command line or environment, next to the ones from the file they replace,
+// so saving the configuration does not persist them.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Overrides {
+    db_path: Option<(PathBuf, PathBuf)>,
+    workspace_id: Option<(Uuid, Uuid)>,
+}
+
+fn current_version() -> String {
+    String::from(env!("CARGO_PKG_VERSION"))
+}
+
+// Read only, an encrypted database without its passphrase or a missing file has no workspace to offer.
+fn first_workspace_id(db_path: &Path) -> Option<Uuid> {
+    let connection = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY).ok()?;
+    let workspaces = load_workspaces(Uuid::nil(), &connection).ok()?;
+
+    workspaces.first().map(|workspace| workspace.id)
 }
 
 impl Configuration {
-    fn config_path(dev_mode: bool) -> PathBuf {
+    // With --portable everything lives in a folder next to the executable.
+    fn data_dir(portable: bool) -> PathBuf {
+        if portable {
+            let executable = std::env::current_exe().expect("Could not locate executable");
+            let mut data_dir = executable.parent().expect("Could not locate executable directory").to_path_buf();
+            data_dir.push("my-blocks-data");
+
+            return data_dir;
+        }
+
+        let mut data_dir = PathBuf::new();
+        data_dir.push(dirs::home_dir().expect("Could not load home dir"));
+        data_dir.push(".config/.my-blocks");
 
-        if dev_mode {
+        data_dir
+    }
+
+    fn config_path(dev_mode: bool, portable: bool) -> PathBuf {
+
+        if dev_mode && !portable {
             let mut config_path = PathBuf::new();
             config_path.push(".config.toml");
 
             return config_path;
         }
 
-        let mut config_path = PathBuf::new();
-        config_path.push(dirs::home_dir().expect("Could not load home dir"));
-        config_path.push(".config/.my-blocks/config.toml");
+        let mut config_path = Configuration::data_dir(portable);
+        config_path.push("config.toml");
 
-        println!("Loading config_path {:?}", config_path);
+        log::debug!("Loading config_path {:?}", config_path);
 
         if let Some(parent) = config_path.parent() {
             if !parent.exists() {
                 log::info!("Creating configuration directory for {:?}", &config_path);
                 std::fs::create_dir_all(parent).expect("Could not create configuration directory");
-                println!("Directory created: {:?}", parent);
+                log::debug!("Directory created: {:?}", parent);
             }
         }
 
@@ -52,23 +130,22 @@ impl Configuration {
         config_path
     }
 
-    fn db_path(dev_mode: bool) -> PathBuf {
+    fn db_path(dev_mode: bool, portable: bool) -> PathBuf {
 
-        if dev_mode {
+        if dev_mode && !portable {
             let mut config_path = PathBuf::new();
             config_path.push("file.db");
 
             return config_path;
         }
 
-        let mut db_path = PathBuf::new();
-        db_path.push(dirs::home_dir().expect("Could not load home dir"));
-        db_path.push(".config/.my-blocks/db.sqlite");
+        let mut db_path = Configuration::data_dir(portable);
+        db_path.push("db.sqlite");
 
         if let Some(parent) = db_path.parent() {
             if !parent.exists() {
                 std::fs::create_dir_all(parent).expect("Could not create configuration directory");
-                println!("Directory created: {:?}", parent);
+                log::debug!("Directory created: {:?}", parent);
             }
         }
 
@@ -79,37 +156,114 @@ impl Configuration {
         db_path
     }
 
-    fn load_from_file(dev_mode: bool) -> Result<Self, String> {
-        let config_path = Configuration::config_path(dev_mode);
+    fn load_from_file(config_path: PathBuf, dev_mode: bool, portable: bool) -> Result<Self, String> {
         log::debug!("Loading config from {:?}", &config_path);
-        let config_str = std::fs::read_to_string(&config_path);
-
-        match config_str {
-            Ok(config_str) => {
-                log::debug!("Configuration successfully loaded from file");
-                match toml::from_str(&config_str) {
-                    Ok(config) => Ok(config),
-                    Err(e) => {
-                        log::error!("Could not parse config file: {:?}", e);
-                        Err(String::from("Could not parse config file"))
-                    }
-                }
-            },
-            Err(_e) => {
+
+        // An empty file is what `config_path` creates on first run.
+        let config_str = match std::fs::read_to_string(&config_path) {
+            Ok(config_str) if !config_str.trim().is_empty() => config_str,
+            _ => {
                 log::debug!("Configuration file not found, bootstrapping new configuration");
-                Ok(Configuration::bootstrap(dev_mode).unwrap())
+                return Configuration::bootstrap(config_path, dev_mode, Configuration::db_path(dev_mode, portable), Uuid::now_v7());
+            }
+        };
+        log::debug!("Configuration successfully loaded from file");
+
+        match Configuration::parse(&config_str) {
+            Ok((config, upgraded_from)) => {
+                // The file that was read is the one to save to, wherever it was copied from.
+                let config = Configuration { config_path, ..config };
+
+                if let Some(upgraded_from) = upgraded_from {
+                    log::info!("Saving configuration upgraded from schema version {}", upgraded_from);
+                    config.save()?;
+                }
+
+                Ok(config)
+            }
+            Err(e) => {
+                log::error!("Could not parse config file: {}", e);
+                Configuration::back_up_corrupt_file(&config_path)?;
+                Configuration::recover(config_path, &config_str, dev_mode, portable)
             }
         }
     }
 
-    fn bootstrap (dev_mode: bool) -> Result<Self, String> {
+    // Starts over from a file that can not be read, keeping its database and workspace where their lines still
+    // parse. Without a workspace id the first workspace of the database is used, rather than a new empty one.
+    fn recover(config_path: PathBuf, config_str: &str, dev_mode: bool, portable: bool) -> Result<Self, String> {
+        let recovered: toml::Table = config_str
+            .lines()
+            .take_while(|line| !line.trim_start().starts_with('['))
+            .filter_map(|line| toml::from_str::<toml::Table>(line).ok())
+            .flatten()
+            .collect();
+
+        let db_path = match recovered.get("dbPath").and_then(toml::Value::as_str) {
+            Some(db_path) => PathBuf::from(db_path),
+            None => Configuration::db_path(dev_mode, portable),
+        };
+        let workspace_id = recovered
+            .get("workspaceId")
+            .and_then(toml::Value::as_str)
+            .and_then(|workspace_id| Uuid::parse_str(workspace_id).ok())
+            .or_else(|| first_workspace_id(&db_path))
+            .unwrap_or_else(Uuid::now_v7);
+        log::warn!("Recovered configuration with database {:?} and workspace {}", &db_path, workspace_id);
+
+        Configuration::bootstrap(config_path, dev_mode, db_path, workspace_id)
+    }
+
+    // Returns the configuration and, when it had to be upgraded, the schema version of the file.
+    fn parse(config_str: &str) -> Result<(Self, Option<u32>), String> {
+        let mut table: toml::Table = toml::from_str(config_str).map_err(|e| e.to_string())?;
+        let upgraded_from = upgrade::upgrade(&mut table);
+
+        let config = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e: toml::de::Error| e.to_string())?;
+
+        Ok((config, upgraded_from))
+    }
+
+    // Moves a file that can not be read out of the way, so a fresh configuration can be written in its place.
+    fn back_up_corrupt_file(config_path: &PathBuf) -> Result<(), String> {
+        let mut backup_name = config_path.file_name().unwrap_or_default().to_os_string();
+        backup_name.push(format!(".{}.corrupt", chrono::Utc::now().format("%Y%m%dT%H%M%S")));
+        let backup_path = config_path.with_file_name(backup_name);
+
+        match std::fs::rename(config_path, &backup_path) {
+            Ok(_) => {
+                log::warn!("Backed up unreadable configuration to {:?}", &backup_path);
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("Could not back up config file: {:?}", e);
+                Err(String::from("Could not back up config file"))
+            }
+        }
+    }
+
+    fn bootstrap (config_path: PathBuf, dev_mode: bool, db_path: PathBuf, workspace_id: Uuid) -> Result<Self, String> {
+
+        if let Some(parent) = config_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).expect("Could not create configuration directory");
+        }
 
         let config = Configuration {
-            version: String::from(env!("CARGO_PKG_VERSION")),
+            version: current_version(),
+            schema_version: upgrade::SCHEMA_VERSION,
             development_mode: dev_mode,
-            config_path: Configuration::config_path(dev_mode),
-            db_path: Configuration::db_path(dev_mode),
-            workspace_id: Uuid::now_v7()
+            config_path,
+            db_path,
+            workspace_id,
+            recent_databases: Vec::new(),
+            preferences: Preferences::default(),
+            database: DatabaseSettings::default(),
+            backups: BackupSettings::default(),
+            run_migrations: false,
+            overrides: Overrides::default(),
+            passphrase: None,
         };
 
         config.save().expect("Could not save config file");
@@ -117,9 +271,51 @@ impl Configuration {
         Ok(config)
     }
 
-    fn save(&self) -> Result<(), String> {
+    fn apply_arguments(&mut self, arguments: &Arguments) {
+        if let Some(db_path) = &arguments.db_path {
+            log::debug!("Using database {:?} instead of {:?}", db_path, &self.db_path);
+            self.overrides.db_path = Some((db_path.clone(), self.db_path.clone()));
+            self.db_path = db_path.clone();
+        }
+
+        if let Some(workspace_id) = arguments.workspace {
+            log::debug!("Using workspace {} instead of {}", workspace_id, self.workspace_id);
+            self.overrides.workspace_id = Some((workspace_id, self.workspace_id));
+            self.workspace_id = workspace_id;
+        }
+
+        self.run_migrations = arguments.run_migrations;
+        self.passphrase = arguments.passphrase.clone();
+    }
+
+    pub fn remember_database(&mut self, db_path: PathBuf) {
+        self.recent_databases.retain(|recent| *recent != db_path);
+        self.recent_databases.insert(0, db_path);
+        self.recent_databases.truncate(RECENT_DATABASES_LIMIT);
+    }
+
+    // Overridden values are written back as they were in the file, unless they were changed since.
+    fn persisted(&self) -> Configuration {
+        let mut persisted = self.clone();
+        if let Some((db_path, file_db_path)) = &self.overrides.db_path {
+            if persisted.db_path == *db_path {
+                persisted.db_path = file_db_path.clone();
+            }
+        }
+        if let Some((workspace_id, file_workspace_id)) = self.overrides.workspace_id {
+            if persisted.workspace_id == workspace_id {
+                persisted.workspace_id = file_workspace_id;
+            }
+        }
+
+        persisted
+    }
+
+    pub fn save(&self) -> Result<(), String> {
         let config_path = PathBuf::from(&self.config_path);
-        let config_str = toml::to_string(&self).expect("Could not serialize config");
+        let persisted = self.persisted();
+
+        let config_str = toml::to_string(&persisted).expect("Could not serialize config");
 
         match std::fs::write(&config_path, config_str) {
             Ok(_) => Ok(()),
@@ -130,13 +326,20 @@ impl Configuration {
         }
     }
 
-    pub fn init() -> Result<Self, String> {
+    // Command line arguments and environment variables win over the file, which wins over the defaults.
+    pub fn init(arguments: Arguments) -> Result<Self, String> {
         let dev_mode = cfg!(debug_assertions);
-        plogger::init(dev_mode);
+        logging::init(dev_mode, arguments.log_level);
         log::debug!("Logger initialised");
-        log::debug!("Initializing configuration with dev mode - {:?}", dev_mode);
+        log::debug!("Initializing configuration with dev mode - {:?} and arguments {:?}", dev_mode, &arguments);
+
+        let config_path = match &arguments.config_path {
+            Some(config_path) => config_path.clone(),
+            None => Configuration::config_path(dev_mode, arguments.portable),
+        };
 
-        let config = Configuration::load_from_file(dev_mode).expect("Could not load configuration");
+        let mut config = Configuration::load_from_file(config_path, dev_mode, arguments.portable).expect("Could not load configuration");
+        config.apply_arguments(&arguments);
 
         log::debug!("Configuration initialised - {:?}", config);
 
@@ -145,8 +348,32 @@ impl Configuration {
 }
 
 #[tauri::command]
-pub fn load_configuration_command(configuration: State<Configuration>) -> String {
+pub fn load_configuration_command(configuration: State<RwLock<Configuration>>) -> String {
     log::debug!("Running load_configuration_command. {:?}", configuration);
 
-    serde_json::to_string(&configuration.inner()).unwrap()
+    serde_json::to_string(&*configuration.read().unwrap()).unwrap()
+}
+
+#[tauri::command]
+pub fn update_configuration_command(
+    preferences: Preferences,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running update_configuration_command with {:?}", preferences);
+    let connection = db.get()?;
+
+    preferences.validate(&connection)?;
+
+    let mut configuration = configuration.write().unwrap();
+
+    let mut updated = configuration.clone();
+    updated.preferences = preferences;
+    updated.save()?;
+    *configuration = updated;
+
+    emit_app_event(&app, CONFIGURATION_CHANGED_EVENT, configuration.clone());
+
+    Ok(serde_json::to_string(&*configuration).unwrap())
 }
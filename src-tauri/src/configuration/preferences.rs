@@ -0,0 +1,103 @@
+use chrono::format::{Item, StrftimeItems};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const MIN_EDITOR_FONT_SIZE: u32 = 8;
+const MAX_EDITOR_FONT_SIZE: u32 = 72;
+const MAX_TRASH_RETENTION_DAYS: u32 = 3650;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    System,
+    Light,
+    Dark,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WeekStart {
+    Monday,
+    Saturday,
+    Sunday,
+}
+
+// Settings the user changes from the app through `update_configuration_command`.
+// Every field has a default, so files written before a preference existed still load.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Preferences {
+    pub theme: Theme,
+    // Page shown when the app opens, the workspace home page when unset.
+    #[serde(rename = "landingPageId", skip_serializing_if = "Option::is_none")]
+    pub landing_page_id: Option<Uuid>,
+    // A chrono strftime pattern.
+    #[serde(rename = "dateFormat")]
+    pub date_format: String,
+    #[serde(rename = "weekStart")]
+    pub week_start: WeekStart,
+    #[serde(rename = "editorFontFamily")]
+    pub editor_font_family: String,
+    #[serde(rename = "editorFontSize")]
+    pub editor_font_size: u32,
+    // Days deleted blocks are kept before they are purged, 0 keeps them forever.
+    #[serde(rename = "trashRetentionDays")]
+    pub trash_retention_days: u32,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences {
+            theme: Theme::System,
+            landing_page_id: None,
+            date_format: String::from("%Y-%m-%d"),
+            week_start: WeekStart::Monday,
+            editor_font_family: String::from("system-ui"),
+            editor_font_size: 16,
+            trash_retention_days: 30,
+        }
+    }
+}
+
+impl Preferences {
+    pub fn validate(&self, connection: &Connection) -> Result<(), String> {
+        if self.date_format.trim().is_empty()
+            || StrftimeItems::new(&self.date_format).any(|item| matches!(item, Item::Error))
+        {
+            return Err(format!("Invalid date format {}", self.date_format));
+        }
+
+        // The family ends up in a CSS declaration.
+        let font_family = self.editor_font_family.trim();
+        if font_family.is_empty() || font_family.contains([';', '{', '}', '<', '>']) {
+            return Err(format!("Invalid editor font {}", self.editor_font_family));
+        }
+
+        if !(MIN_EDITOR_FONT_SIZE..=MAX_EDITOR_FONT_SIZE).contains(&self.editor_font_size) {
+            return Err(format!(
+                "Editor font size must be between {} and {}",
+                MIN_EDITOR_FONT_SIZE, MAX_EDITOR_FONT_SIZE
+            ));
+        }
+
+        if self.trash_retention_days > MAX_TRASH_RETENTION_DAYS {
+            return Err(format!("Trash retention can not exceed {} days", MAX_TRASH_RETENTION_DAYS));
+        }
+
+        if let Some(landing_page_id) = self.landing_page_id {
+            let block_type: Option<String> = connection
+                .query_row(
+                    "SELECT block_type FROM blocks WHERE id = ?1",
+                    [landing_page_id.to_string()],
+                    |row| row.get(0),
+                )
+                .ok();
+            if block_type.as_deref() != Some("page") {
+                return Err(String::from("Landing page not found"));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use toml::{Table, Value};
+
+pub const SCHEMA_VERSION_KEY: &str = "schemaVersion";
+
+// Each step takes a file from the version matching its position to the next one.
+// Append a step whenever a field is renamed or needs a value that serde defaults can not provide.
+// New fields with a serde default need no step.
+const UPGRADES: &[fn(&mut Table)] = &[];
+
+pub const SCHEMA_VERSION: u32 = UPGRADES.len() as u32;
+
+// Files written before the schema was versioned have no schemaVersion and count as version 0.
+pub fn schema_version(table: &Table) -> u32 {
+    table
+        .get(SCHEMA_VERSION_KEY)
+        .and_then(Value::as_integer)
+        .map(|version| version.max(0) as u32)
+        .unwrap_or(0)
+}
+
+// Brings the file up to date one version at a time, returning the version it started from when anything changed.
+pub fn upgrade(table: &mut Table) -> Option<u32> {
+    let from = schema_version(table);
+
+    if from > SCHEMA_VERSION {
+        log::warn!(
+            "Configuration file has schema version {} but this build only knows up to {}, unknown fields will be lost when saving",
+            from,
+            SCHEMA_VERSION
+        );
+        return None;
+    }
+    if from == SCHEMA_VERSION {
+        return None;
+    }
+
+    for (version, upgrade) in UPGRADES.iter().enumerate().skip(from as usize) {
+        log::info!("Upgrading configuration from schema version {} to {}", version, version + 1);
+        upgrade(table);
+        table.insert(String::from(SCHEMA_VERSION_KEY), Value::Integer(version as i64 + 1));
+    }
+
+    table.insert(String::from("version"), Value::String(String::from(env!("CARGO_PKG_VERSION"))));
+
+    Some(from)
+}
@@ -0,0 +1,162 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::{mpsc, RwLock};
+use std::time::Duration;
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use rusqlite::Connection;
+use tauri::{AppHandle, Manager};
+
+use super::Configuration;
+use crate::events::{emit_app_event, CONFIGURATION_CHANGED_EVENT, CONFIGURATION_INVALID_EVENT, WORKSPACE_SWITCHED_EVENT};
+use crate::storage::Database;
+use crate::workspaces::load_workspaces;
+
+// Editors save in bursts of events, the file is read once they settle.
+const SETTLE_DELAY: Duration = Duration::from_millis(250);
+
+// Reloads the configuration whenever its file is edited outside of the app.
+pub fn watch_configuration(app: AppHandle) -> Result<(), String> {
+    let config_path = app.state::<RwLock<Configuration>>().read().unwrap().config_path.clone();
+    let file_name = config_path.file_name().map(OsString::from).ok_or_else(|| String::from("Invalid config path"))?;
+
+    // Editors often replace the file instead of writing to it, which a watch on the file itself would miss.
+    let directory = match config_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(|e| {
+        log::error!("Could not create configuration watcher: {:?}", e);
+        String::from("Could not watch config file")
+    })?;
+    watcher.watch(&directory, RecursiveMode::NonRecursive).map_err(|e| {
+        log::error!("Could not watch {:?}: {:?}", &directory, e);
+        String::from("Could not watch config file")
+    })?;
+    log::debug!("Watching {:?} for configuration changes", &config_path);
+
+    std::thread::spawn(move || {
+        // Watching stops when the watcher is dropped, so it lives as long as this thread.
+        let _watcher = watcher;
+
+        while let Ok(event) = receiver.recv() {
+            if !concerns_file(&event, &file_name) {
+                continue;
+            }
+            while receiver.recv_timeout(SETTLE_DELAY).is_ok() {}
+
+            reload(&app);
+        }
+    });
+
+    Ok(())
+}
+
+fn concerns_file(event: &notify::Result<Event>, file_name: &OsString) -> bool {
+    match event {
+        Ok(event) => {
+            !matches!(event.kind, EventKind::Access(_))
+                && event.paths.iter().any(|path| path.file_name() == Some(file_name.as_os_str()))
+        }
+        Err(e) => {
+            log::error!("Configuration watcher error: {:?}", e);
+            false
+        }
+    }
+}
+
+fn reload(app: &AppHandle) {
+    let configuration = app.state::<RwLock<Configuration>>();
+    let db = app.state::<Database>();
+
+    let current = configuration.read().unwrap().clone();
+    // A locked database, or one being switched, can not check the landing page. The next edit is picked up again.
+    let connection = match db.get() {
+        Ok(connection) => connection,
+        Err(e) => {
+            log::error!("Skipping configuration reload: {}", e);
+            return;
+        }
+    };
+
+    let reloaded = match read_configuration(&current, &connection) {
+        Ok(Some(reloaded)) => reloaded,
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("Keeping the current configuration, the edited file is invalid: {}", e);
+            emit_app_event(app, CONFIGURATION_INVALID_EVENT, e);
+            return;
+        }
+    };
+
+    // Saves made by the app itself come back through here and change nothing.
+    if reloaded == current {
+        return;
+    }
+
+    // Another workspace of the open database, such as one the CLI restored, is switched to like `switch_workspace_command` does.
+    let switched = match reloaded.workspace_id != current.workspace_id {
+        true => match load_workspaces(reloaded.workspace_id, &connection) {
+            Ok(workspaces) => workspaces.into_iter().find(|workspace| workspace.active),
+            Err(e) => {
+                log::error!("Skipping configuration reload: {}", e);
+                return;
+            }
+        },
+        false => None,
+    };
+    drop(connection);
+
+    log::info!("Reloaded configuration from {:?}", &current.config_path);
+    *configuration.write().unwrap() = reloaded.clone();
+
+    emit_app_event(app, CONFIGURATION_CHANGED_EVENT, reloaded);
+    if let Some(workspace) = switched {
+        log::info!("Switched to workspace {}", workspace.id);
+        emit_app_event(app, WORKSPACE_SWITCHED_EVENT, workspace);
+    }
+}
+
+// Returns None while the file is being written.
+fn read_configuration(current: &Configuration, connection: &Connection) -> Result<Option<Configuration>, String> {
+    let config_str = std::fs::read_to_string(&current.config_path).map_err(|e| format!("Could not read config file: {}", e))?;
+    if config_str.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let (config, _) = Configuration::parse(&config_str)?;
+    config.preferences.validate(connection)?;
+    config.database.validate()?;
+
+    // Switching databases needs more than new state, it happens through its command or on restart. Until
+    // then the running values are kept like command line overrides, so saving does not undo the edit. A
+    // workspace of the open database is switched to right away, others wait for the next start as well.
+    let mut overrides = current.overrides.clone();
+    let mut workspace_id = current.workspace_id;
+    if config.db_path != current.persisted().db_path {
+        log::warn!("dbPath changed to {:?}, it applies on the next start", &config.db_path);
+        overrides.db_path = Some((current.db_path.clone(), config.db_path.clone()));
+    }
+    if config.workspace_id != current.persisted().workspace_id {
+        if load_workspaces(config.workspace_id, connection)?.iter().any(|workspace| workspace.active) {
+            workspace_id = config.workspace_id;
+            overrides.workspace_id = None;
+        } else {
+            log::warn!("workspaceId changed to {}, it is not in the open database and applies on the next start", config.workspace_id);
+            overrides.workspace_id = Some((current.workspace_id, config.workspace_id));
+        }
+    }
+
+    Ok(Some(Configuration {
+        development_mode: current.development_mode,
+        config_path: current.config_path.clone(),
+        db_path: current.db_path.clone(),
+        workspace_id,
+        run_migrations: current.run_migrations,
+        overrides,
+        passphrase: current.passphrase.clone(),
+        ..config
+    }))
+}
@@ -0,0 +1,59 @@
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy)]
+pub enum BlockEvent {
+    Created,
+    Updated,
+    Moved,
+    Deleted,
+}
+
+impl BlockEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlockEvent::Created => "block-created",
+            BlockEvent::Updated => "block-updated",
+            BlockEvent::Moved => "block-moved",
+            BlockEvent::Deleted => "block-deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockEventPayload {
+    pub block_ids: Vec<Uuid>,
+    pub parent_ids: Vec<Uuid>,
+}
+
+// Broadcasts to every window, so views that are not the one issuing the command stay in sync.
+// A failed emit is logged and never fails the command that already mutated the database.
+pub fn emit_block_event(app: &AppHandle, event: BlockEvent, block_ids: Vec<Uuid>, parent_ids: Vec<Uuid>) {
+    log::debug!("Emitting {} for blocks {:?}", event.name(), block_ids);
+
+    let payload = BlockEventPayload {
+        block_ids,
+        parent_ids,
+    };
+
+    if let Err(e) = app.emit(event.name(), payload) {
+        log::error!("Could not emit {}: {:?}", event.name(), e);
+    }
+}
+
+pub const WORKSPACE_SWITCHED_EVENT: &str = "workspace-switched";
+pub const DATABASE_OPENED_EVENT: &str = "database-opened";
+pub const CONFIGURATION_CHANGED_EVENT: &str = "configuration-changed";
+pub const CONFIGURATION_INVALID_EVENT: &str = "configuration-invalid";
+pub const BACKUP_RESTORED_EVENT: &str = "backup-restored";
+pub const DATABASE_COMPACTION_EVENT: &str = "database-compaction";
+
+// Broadcasts an application level event, logging instead of failing like `emit_block_event`.
+pub fn emit_app_event<S: Serialize + Clone>(app: &AppHandle, event: &str, payload: S) {
+    log::debug!("Emitting {}", event);
+
+    if let Err(e) = app.emit(event, payload) {
+        log::error!("Could not emit {}: {:?}", event, e);
+    }
+}
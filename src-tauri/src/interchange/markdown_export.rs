@@ -0,0 +1,438 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::RwLock;
+
+use tauri::State;
+use uuid::Uuid;
+
+use crate::attachments::{AttachmentStore, ATTACHMENT_SCHEME};
+use crate::blocks::rich_text::RichText;
+use crate::blocks::{Block, BlockContent};
+use crate::configuration::Configuration;
+use crate::storage::Database;
+
+// Attachments are copied into this directory of an export, pages link to them there.
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+// Where every exported page ends up, relative to the export directory.
+pub struct PageFiles<'a> {
+    pub pages: Vec<(&'a Block, PathBuf)>,
+    pub files: HashMap<Uuid, PathBuf>,
+}
+
+pub fn page_title(page: &Block) -> String {
+    page.block_contents.contents.clone().unwrap_or_default()
+}
+
+// Names Windows reserves for devices, also when followed by an extension.
+const RESERVED_FILE_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// Turns a title into a file name that is valid on every platform.
+pub fn file_name_for_title(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => '-',
+            c => c,
+        })
+        .collect();
+    let name = name.trim().trim_matches('.').trim().to_string();
+
+    if name.is_empty() {
+        return String::from("Untitled");
+    }
+
+    let device = name.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_FILE_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(device)) {
+        return format!("{}-{}", device, &name[device.len()..]);
+    }
+    name
+}
+
+// Names are compared ignoring case, `Notes` and `notes` are the same file on macOS and Windows.
+fn unique_file_stem(page: &Block, dir: &Path, used: &mut HashSet<String>) -> String {
+    let name = file_name_for_title(&page_title(page));
+
+    if used.insert(dir.join(&name).to_string_lossy().to_lowercase()) {
+        return name;
+    }
+
+    let name = format!("{} ({})", name, &page.id.simple().to_string()[..8]);
+    used.insert(dir.join(&name).to_string_lossy().to_lowercase());
+    name
+}
+
+// Pages nested below `block`, also those below its other blocks, but not those below its pages.
+pub fn child_pages(block: &Block) -> Vec<&Block> {
+    let mut pages = Vec::new();
+    for child in &block.children {
+        if child.block_type == "page" {
+            pages.push(child);
+        } else {
+            pages.extend(child_pages(child));
+        }
+    }
+    pages
+}
+
+fn collect_page_files<'a>(page: &'a Block, dir: &Path, extension: &str, used: &mut HashSet<String>, page_files: &mut PageFiles<'a>) {
+    let stem = unique_file_stem(page, dir, used);
+    let file = dir.join(format!("{}.{}", stem, extension));
+
+    page_files.files.insert(page.id, file.clone());
+    page_files.pages.push((page, file));
+
+    let children_dir = dir.join(stem);
+    for child_page in child_pages(page) {
+        collect_page_files(child_page, &children_dir, extension, used, page_files);
+    }
+}
+
+// A file for `page` and every page below it, each page's sub pages in a folder named after it.
+pub fn page_files<'a>(page: &'a Block, extension: &str) -> PageFiles<'a> {
+    let mut page_files = PageFiles {
+        pages: Vec::new(),
+        files: HashMap::new(),
+    };
+    collect_page_files(page, Path::new(""), extension, &mut HashSet::new(), &mut page_files);
+
+    page_files
+}
+
+// Relative link from a file in `from_dir` to `to`, both relative to the export directory.
+pub fn relative_link(from_dir: &Path, to: &Path) -> String {
+    let from: Vec<Component> = from_dir.components().collect();
+    let to_components: Vec<Component> = to.components().collect();
+
+    let common = from.iter().zip(to_components.iter()).take_while(|(a, b)| a == b).count();
+
+    let mut parts: Vec<String> = vec![String::from(".."); from.len() - common];
+    parts.extend(
+        to_components[common..]
+            .iter()
+            .map(|component| component.as_os_str().to_string_lossy().to_string()),
+    );
+
+    parts.join("/")
+}
+
+fn rich_text_markdown(content: &BlockContent, from_dir: &Path, files: &HashMap<Uuid, PathBuf>) -> String {
+    let mut rich_text = match &content.rich_text {
+        Some(rich_text) => rich_text.clone(),
+        None => RichText::plain(content.contents.as_deref().unwrap_or_default()),
+    };
+
+    for span in rich_text.spans.iter_mut() {
+        if let Some(file) = span.page_reference.and_then(|page_id| files.get(&page_id)) {
+            span.link = Some(relative_link(from_dir, file));
+            span.page_reference = None;
+        }
+    }
+
+    escape_line_starts(&rich_text.to_markdown())
+}
+
+// Text at the start of a line that would turn it into a heading, quote, list item or rule when read back.
+fn escape_line_starts(markdown: &str) -> String {
+    let lines: Vec<String> = markdown
+        .split('\n')
+        .map(|line| {
+            let text = line.trim_start_matches(' ');
+            let indent = &line[..line.len() - text.len()];
+            let digits = text.chars().take_while(char::is_ascii_digit).count();
+
+            match text.chars().next() {
+                Some('#' | '>' | '-' | '+' | '=') => format!("{}\\{}", indent, text),
+                Some(_) if (1..=9).contains(&digits) && is_list_delimiter(&text[digits..]) => {
+                    format!("{}{}\\{}", indent, &text[..digits], &text[digits..])
+                }
+                _ => line.to_string(),
+            }
+        })
+        .collect();
+
+    lines.join("\n")
+}
+
+// `.` or `)` ending the line or followed by a space, as after the number of an ordered list item.
+fn is_list_delimiter(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some('.' | ')')) && matches!(chars.next(), None | Some(' ' | '\t'))
+}
+
+fn code_fence(code: &str) -> String {
+    let longest_run = code
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+
+    "`".repeat(longest_run.max(2) + 1)
+}
+
+// The Markdown of a single block, without its children. Continuation lines are not indented yet.
+fn block_markdown(block: &Block, from_dir: &Path, files: &HashMap<Uuid, PathBuf>) -> String {
+    let content = &block.block_contents;
+    let property = |name: &str| content.properties.get(name);
+
+    match content.content_type.as_str() {
+        "page" => {
+            let title = RichText::plain(&page_title(block)).to_markdown();
+            match files.get(&block.id) {
+                Some(file) => format!("[{}]({})", title, link_target(from_dir, file)),
+                None => title,
+            }
+        }
+        "heading" => {
+            let level = property("level").and_then(|level| level.as_u64()).unwrap_or(1).clamp(1, 6) as usize;
+            format!("{} {}", "#".repeat(level), rich_text_markdown(content, from_dir, files))
+        }
+        "quote" => format!("> {}", rich_text_markdown(content, from_dir, files)),
+        "code" => {
+            let code = content.contents.clone().unwrap_or_default();
+            let fence = code_fence(&code);
+            let language = property("language").and_then(|language| language.as_str()).unwrap_or_default();
+            format!("{}{}\n{}\n{}", fence, language, code, fence)
+        }
+        "image" => {
+            let alt = RichText::plain(property("alt").and_then(|alt| alt.as_str()).unwrap_or_default()).to_markdown();
+            let url = content.contents.clone().unwrap_or_default();
+            let url = match url.strip_prefix(ATTACHMENT_SCHEME) {
+                Some(name) => relative_link(from_dir, &Path::new(ATTACHMENTS_DIR).join(name)),
+                None => url,
+            };
+            match property("title").and_then(|title| title.as_str()) {
+                Some(title) => format!("![{}](<{}> \"{}\")", alt, url, title.replace('"', "\\\"")),
+                None => format!("![{}](<{}>)", alt, url),
+            }
+        }
+        "table" => table_markdown(content),
+        _ => rich_text_markdown(content, from_dir, files),
+    }
+}
+
+fn table_cell(value: &serde_json::Value) -> String {
+    let text = match value {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Null => String::new(),
+        value => value.to_string(),
+    };
+
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+fn table_markdown(content: &BlockContent) -> String {
+    let cells = |name: &str| -> Vec<serde_json::Value> {
+        content.properties.get(name).and_then(|value| value.as_array()).cloned().unwrap_or_default()
+    };
+    let columns = cells("columns");
+    let row_markdown = |row: &[serde_json::Value]| {
+        let cells: Vec<String> = (0..columns.len()).map(|i| table_cell(row.get(i).unwrap_or(&serde_json::Value::Null))).collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let mut lines = Vec::new();
+    if let Some(title) = content.contents.as_deref().filter(|title| !title.is_empty()) {
+        lines.push(format!("**{}**\n", RichText::plain(title).to_markdown()));
+    }
+    lines.push(row_markdown(&columns));
+    lines.push(format!("|{}", " --- |".repeat(columns.len())));
+    for row in cells("rows") {
+        lines.push(row_markdown(row.as_array().map(Vec::as_slice).unwrap_or_default()));
+    }
+
+    lines.join("\n")
+}
+
+fn link_target(from_dir: &Path, file: &Path) -> String {
+    let link = relative_link(from_dir, file);
+    if link.contains(' ') {
+        return format!("<{}>", link);
+    }
+    link
+}
+
+fn indent_continuation(markdown: &str, indent: &str, hard_breaks: bool) -> String {
+    let separator = if hard_breaks { format!("\\\n{}", indent) } else { format!("\n{}", indent) };
+    markdown.split('\n').collect::<Vec<&str>>().join(&separator)
+}
+
+fn list_marker(block: &Block, position: usize) -> String {
+    let content = &block.block_contents;
+
+    if content.content_type == "todo" {
+        let checked = content.properties.get("checked").and_then(|checked| checked.as_bool()).unwrap_or(false);
+        return String::from(if checked { "- [x] " } else { "- [ ] " });
+    }
+
+    match content.properties.get("list").and_then(|list| list.as_str()) {
+        Some("ordered") => format!("{}. ", position + 1),
+        _ => String::from("- "),
+    }
+}
+
+fn write_list(blocks: &[Block], indent: &str, from_dir: &Path, files: &HashMap<Uuid, PathBuf>, markdown: &mut String) {
+    let mut position = 0;
+
+    for block in blocks {
+        let marker = list_marker(block, position);
+        position = if marker.ends_with(". ") { position + 1 } else { 0 };
+        // Todo markers are a bullet plus a checkbox, their content lines up with the bullet.
+        let child_indent = format!("{}{}", indent, " ".repeat(if marker.starts_with("- [") { 2 } else { marker.len() }));
+        let is_code = block.block_contents.content_type == "code";
+
+        let line = indent_continuation(&block_markdown(block, from_dir, files), &child_indent, !is_code);
+        markdown.push_str(&format!("{}{}{}\n", indent, marker, line));
+
+        if block.block_type != "page" {
+            write_list(&block.children, &child_indent, from_dir, files, markdown);
+        }
+    }
+}
+
+fn is_list_item(block: &Block) -> bool {
+    block.block_contents.content_type == "todo" || block.block_contents.properties.contains_key("list")
+}
+
+pub fn page_markdown(page: &Block, file: &Path, files: &HashMap<Uuid, PathBuf>) -> String {
+    let from_dir = file.parent().unwrap_or(Path::new(""));
+    let mut markdown = format!("# {}\n", RichText::plain(&page_title(page)).to_markdown());
+    // Table children are the pages of its rows, they are exported as files of their own.
+    let is_table = |block: &Block| block.block_contents.content_type == "table";
+    let is_list = |block: &Block| block.block_type != "page" && !is_table(block) && (is_list_item(block) || !block.children.is_empty());
+
+    let mut start = 0;
+    while start < page.children.len() {
+        markdown.push('\n');
+
+        // Consecutive list items form one tight list, everything else is separated by a blank line.
+        if is_list(&page.children[start]) {
+            let mut end = start + 1;
+            while end < page.children.len() && is_list(&page.children[end]) {
+                end += 1;
+            }
+            write_list(&page.children[start..end], "", from_dir, files, &mut markdown);
+            start = end;
+            continue;
+        }
+
+        let block = &page.children[start];
+        let is_verbatim = matches!(block.block_contents.content_type.as_str(), "code" | "table");
+        markdown.push_str(&indent_continuation(&block_markdown(block, from_dir, files), "", !is_verbatim));
+        markdown.push('\n');
+        start += 1;
+    }
+
+    markdown
+}
+
+pub fn collect_attachments(blocks: &[Block], names: &mut BTreeSet<String>) {
+    for block in blocks {
+        if block.block_contents.content_type == "image" {
+            if let Some(name) = block.block_contents.contents.as_deref().and_then(|url| url.strip_prefix(ATTACHMENT_SCHEME)) {
+                names.insert(name.to_string());
+            }
+        }
+        collect_attachments(&block.children, names);
+    }
+}
+
+// Copies the attachments of `pages` into the attachments directory of `dir`, returning how many were copied.
+// Names are content hashes, a file already there holds the same content and is kept.
+pub fn copy_attachments(pages: &[(&Block, PathBuf)], dir: &Path, attachments: &AttachmentStore) -> Result<usize, String> {
+    let mut names = BTreeSet::new();
+    for (page, _) in pages {
+        collect_attachments(&page.children, &mut names);
+    }
+
+    let mut copied = 0;
+    for name in &names {
+        let source = match attachments.path_for(&format!("{}{}", ATTACHMENT_SCHEME, name)) {
+            Some(source) if source.is_file() => source,
+            _ => {
+                log::warn!("Attachment {} is missing, not copying it", name);
+                continue;
+            }
+        };
+        let destination = dir.join(ATTACHMENTS_DIR).join(name);
+        if !destination.exists() {
+            std::fs::create_dir_all(dir.join(ATTACHMENTS_DIR)).map_err(|e| {
+                log::error!("Could not create attachments directory in {:?}: {:?}", dir, e);
+                String::from("Could not create attachments directory")
+            })?;
+            std::fs::copy(&source, &destination).map_err(|e| {
+                log::error!("Could not copy attachment {:?}: {:?}", &source, e);
+                String::from("Could not copy attachment")
+            })?;
+        }
+        copied += 1;
+    }
+
+    Ok(copied)
+}
+
+// Writes `page` and every page below it into `dir`, returning the written files. The attachments
+// they show are copied along, the written pages link to the copies.
+pub fn export_page(page: &Block, dir: &Path, attachments: &AttachmentStore) -> Result<Vec<PathBuf>, String> {
+    let page_files = page_files(page, "md");
+
+    // Existing files are never overwritten, an export into a directory holding any of them fails before writing.
+    if let Some((_, file)) = page_files.pages.iter().find(|(_, file)| dir.join(file).exists()) {
+        return Err(format!("Could not export, {} already exists", dir.join(file).display()));
+    }
+
+    let mut written_files = Vec::new();
+    for (page, file) in &page_files.pages {
+        let path = dir.join(file);
+
+        if let Some(parent) = path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                log::error!("Could not create export directory {:?}: {:?}", parent, e);
+                return Err(String::from("Could not create export directory"));
+            }
+        }
+
+        let written = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .and_then(|mut output| output.write_all(page_markdown(page, file, &page_files.files).as_bytes()));
+        if let Err(e) = written {
+            log::error!("Could not write {:?}: {:?}", &path, e);
+            return Err(String::from("Could not write markdown file"));
+        }
+
+        written_files.push(path);
+    }
+    copy_attachments(&page_files.pages, dir, attachments)?;
+
+    Ok(written_files)
+}
+
+#[tauri::command]
+pub fn export_page_markdown_command(
+    page_id: String,
+    path: String,
+    configuration: State<RwLock<Configuration>>,
+    db: State<Database>,
+) -> Result<String, String> {
+    log::debug!("Running export_page_markdown_command for page {} to {}", page_id, path);
+    let connection = db.get()?;
+
+    let page_uuid = Uuid::parse_str(&page_id).map_err(|_| String::from("Invalid page id"))?;
+    let page = match Block::load_by_id(page_uuid, &connection).map_err(|_| String::from("Could not load page"))? {
+        Some(page) => page,
+        None => return Err(String::from("Page not found")),
+    };
+
+    let attachments = AttachmentStore::for_configuration(&configuration.read().unwrap());
+    let written_files = export_page(&page, Path::new(&path), &attachments)?;
+    log::debug!("Exported {} markdown files", written_files.len());
+
+    Ok(serde_json::to_string(&written_files).unwrap())
+}
@@ -0,0 +1,417 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::RwLock;
+
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use rusqlite::Connection;
+use serde_json::json;
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+use super::{is_remote_url, percent_decode, save_imported_blocks, store_local_file, ImportedBlock};
+use crate::attachments::AttachmentStore;
+use crate::blocks::rich_text::{RichText, Span};
+use crate::blocks::{Block, BlockContent};
+use crate::configuration::Configuration;
+use crate::events::{emit_block_event, BlockEvent};
+use crate::storage::Database;
+
+pub fn markdown_options() -> Options {
+    Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH
+}
+
+enum FrameKind {
+    Document,
+    Quote,
+    List { ordered: bool },
+    Item { ordered: bool, checked: Option<bool> },
+}
+
+// A container whose blocks are being collected. List items keep their first paragraph apart,
+// it becomes the item block itself and everything after it becomes its children.
+struct Frame {
+    kind: FrameKind,
+    text: Option<ImportedBlock>,
+    blocks: Vec<ImportedBlock>,
+}
+
+impl Frame {
+    fn new(kind: FrameKind) -> Self {
+        Frame {
+            kind,
+            text: None,
+            blocks: Vec::new(),
+        }
+    }
+}
+
+struct Inline {
+    content_type: String,
+    properties: BTreeMap<String, serde_json::Value>,
+    spans: Vec<Span>,
+}
+
+#[derive(Default)]
+struct Marks {
+    bold: usize,
+    italic: usize,
+    strikethrough: usize,
+    link: Option<String>,
+}
+
+struct CodeBlock {
+    language: Option<String>,
+    text: String,
+}
+
+struct Image {
+    url: String,
+    title: String,
+    alt: String,
+}
+
+struct MarkdownConverter<'a> {
+    resolve_image: &'a dyn Fn(&str) -> String,
+    frames: Vec<Frame>,
+    inline: Option<Inline>,
+    marks: Marks,
+    code: Option<CodeBlock>,
+    image: Option<Image>,
+    images: Vec<ImportedBlock>,
+}
+
+impl<'a> MarkdownConverter<'a> {
+    fn new(resolve_image: &'a dyn Fn(&str) -> String) -> Self {
+        MarkdownConverter {
+            resolve_image,
+            frames: vec![Frame::new(FrameKind::Document)],
+            inline: None,
+            marks: Marks::default(),
+            code: None,
+            image: None,
+            images: Vec::new(),
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start(Tag::Paragraph) | Event::Start(Tag::HtmlBlock) => self.begin_inline(self.paragraph_type()),
+            Event::End(TagEnd::Paragraph) | Event::End(TagEnd::HtmlBlock) => self.end_inline(),
+            Event::Start(Tag::Heading { level, .. }) => {
+                self.begin_inline("heading");
+                if let Some(inline) = self.inline.as_mut() {
+                    inline.properties.insert(String::from("level"), json!(level as u8));
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => self.end_inline(),
+            Event::Start(Tag::BlockQuote(_)) => {
+                self.end_inline();
+                self.frames.push(Frame::new(FrameKind::Quote));
+            }
+            Event::End(TagEnd::BlockQuote) => self.end_container(),
+            Event::Start(Tag::List(start)) => {
+                self.end_inline();
+                self.frames.push(Frame::new(FrameKind::List {
+                    ordered: start.is_some(),
+                }));
+            }
+            Event::End(TagEnd::List(_)) => self.end_container(),
+            Event::Start(Tag::Item) => {
+                self.end_inline();
+                let ordered = matches!(self.current_frame().kind, FrameKind::List { ordered: true });
+                self.frames.push(Frame::new(FrameKind::Item { ordered, checked: None }));
+            }
+            Event::End(TagEnd::Item) => self.end_item(),
+            Event::TaskListMarker(is_checked) => {
+                if let FrameKind::Item { checked, .. } = &mut self.current_frame().kind {
+                    *checked = Some(is_checked);
+                }
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                self.end_inline();
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().map(String::from),
+                    CodeBlockKind::Indented => None,
+                };
+                self.code = Some(CodeBlock {
+                    language,
+                    text: String::new(),
+                });
+            }
+            Event::End(TagEnd::CodeBlock) => self.end_code_block(),
+            Event::Start(Tag::Emphasis) => self.marks.italic += 1,
+            Event::End(TagEnd::Emphasis) => self.marks.italic = self.marks.italic.saturating_sub(1),
+            Event::Start(Tag::Strong) => self.marks.bold += 1,
+            Event::End(TagEnd::Strong) => self.marks.bold = self.marks.bold.saturating_sub(1),
+            Event::Start(Tag::Strikethrough) => self.marks.strikethrough += 1,
+            Event::End(TagEnd::Strikethrough) => {
+                self.marks.strikethrough = self.marks.strikethrough.saturating_sub(1)
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => self.marks.link = Some(dest_url.to_string()),
+            Event::End(TagEnd::Link) => self.marks.link = None,
+            Event::Start(Tag::Image { dest_url, title, .. }) => {
+                self.image = Some(Image {
+                    url: dest_url.to_string(),
+                    title: title.to_string(),
+                    alt: String::new(),
+                })
+            }
+            Event::End(TagEnd::Image) => self.end_image(),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => self.text(&text),
+            Event::Code(code) => self.push_span(&code, true),
+            Event::SoftBreak => self.text(" "),
+            Event::HardBreak => self.text("\n"),
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> Vec<ImportedBlock> {
+        self.end_inline();
+        while self.frames.len() > 1 {
+            self.end_container();
+        }
+
+        self.frames.pop().map(|frame| frame.blocks).unwrap_or_default()
+    }
+
+    fn current_frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("The document frame is never popped")
+    }
+
+    fn paragraph_type(&self) -> &'static str {
+        match self.frames.last().map(|frame| &frame.kind) {
+            Some(FrameKind::Quote) => "quote",
+            _ => "paragraph",
+        }
+    }
+
+    fn push_block(&mut self, block: ImportedBlock) {
+        let frame = self.current_frame();
+
+        let is_item_text = matches!(frame.kind, FrameKind::Item { .. })
+            && frame.text.is_none()
+            && frame.blocks.is_empty()
+            && block.content.content_type == "paragraph";
+
+        if is_item_text {
+            frame.text = Some(block);
+        } else {
+            frame.blocks.push(block);
+        }
+    }
+
+    fn begin_inline(&mut self, content_type: &str) {
+        self.end_inline();
+        self.inline = Some(Inline {
+            content_type: content_type.to_string(),
+            properties: BTreeMap::new(),
+            spans: Vec::new(),
+        });
+    }
+
+    fn end_inline(&mut self) {
+        if let Some(inline) = self.inline.take() {
+            let rich_text = RichText::from_spans(inline.spans);
+
+            if !rich_text.plain_text().trim().is_empty() {
+                let mut content = BlockContent::new(inline.content_type, String::new());
+                content.set_rich_text(rich_text);
+                content.properties = inline.properties;
+                self.push_block(ImportedBlock::new(content));
+            }
+        }
+
+        // Images are blocks of their own, placed right after the text they appeared in.
+        for image in std::mem::take(&mut self.images) {
+            self.push_block(image);
+        }
+    }
+
+    fn end_container(&mut self) {
+        self.end_inline();
+
+        if self.frames.len() > 1 {
+            let frame = self.frames.pop().unwrap();
+            for block in frame.text.into_iter().chain(frame.blocks) {
+                self.push_block(block);
+            }
+        }
+    }
+
+    fn end_item(&mut self) {
+        self.end_inline();
+
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        let mut item = frame
+            .text
+            .unwrap_or_else(|| ImportedBlock::new(BlockContent::new(String::from("paragraph"), String::new())));
+
+        match frame.kind {
+            FrameKind::Item { checked: Some(checked), .. } => {
+                item.content.content_type = String::from("todo");
+                item.content.properties.insert(String::from("checked"), json!(checked));
+            }
+            FrameKind::Item { ordered, .. } => {
+                let list = if ordered { "ordered" } else { "bullet" };
+                item.content.properties.insert(String::from("list"), json!(list));
+            }
+            _ => {}
+        }
+
+        item.children = frame.blocks;
+        self.push_block(item);
+    }
+
+    fn end_code_block(&mut self) {
+        if let Some(code) = self.code.take() {
+            let text = code.text.strip_suffix('\n').unwrap_or(&code.text).to_string();
+            let mut content = BlockContent::new(String::from("code"), text);
+            if let Some(language) = code.language {
+                content.properties.insert(String::from("language"), json!(language));
+            }
+            self.push_block(ImportedBlock::new(content));
+        }
+    }
+
+    fn end_image(&mut self) {
+        if let Some(image) = self.image.take() {
+            let mut content = BlockContent::new(String::from("image"), (self.resolve_image)(&image.url));
+            if !image.alt.is_empty() {
+                content.properties.insert(String::from("alt"), json!(image.alt));
+            }
+            if !image.title.is_empty() {
+                content.properties.insert(String::from("title"), json!(image.title));
+            }
+            self.images.push(ImportedBlock::new(content));
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some(code) = self.code.as_mut() {
+            code.text.push_str(text);
+        } else if let Some(image) = self.image.as_mut() {
+            image.alt.push_str(text);
+        } else {
+            self.push_span(text, false);
+        }
+    }
+
+    fn push_span(&mut self, text: &str, code: bool) {
+        if let Some(image) = self.image.as_mut() {
+            image.alt.push_str(text);
+            return;
+        }
+        if self.inline.is_none() {
+            // Tight list items have their text directly inside the item, without a paragraph.
+            self.begin_inline(self.paragraph_type());
+        }
+
+        let page_reference = self
+            .marks
+            .link
+            .as_deref()
+            .and_then(|link| link.strip_prefix("page:"))
+            .and_then(|page_id| Uuid::parse_str(page_id).ok());
+
+        let span = Span {
+            text: text.to_string(),
+            bold: self.marks.bold > 0,
+            italic: self.marks.italic > 0,
+            code,
+            strikethrough: self.marks.strikethrough > 0,
+            link: if page_reference.is_some() { None } else { self.marks.link.clone() },
+            page_reference,
+        };
+
+        if let Some(inline) = self.inline.as_mut() {
+            inline.spans.push(span);
+        }
+    }
+}
+
+// Converts a Markdown document into blocks. `resolve_image` maps image urls as written in the
+// document to the url stored on the image block.
+pub fn parse_markdown(markdown: &str, resolve_image: &dyn Fn(&str) -> String) -> Vec<ImportedBlock> {
+    let mut converter = MarkdownConverter::new(resolve_image);
+
+    for event in Parser::new_ext(markdown, markdown_options()) {
+        converter.handle(event);
+    }
+
+    converter.finish()
+}
+
+// Relative image paths are resolved against the directory of the imported file.
+fn resolve_local_image(attachments: &AttachmentStore, base_dir: &Path, url: &str) -> String {
+    if is_remote_url(url) {
+        return url.to_string();
+    }
+
+    store_local_file(attachments, &base_dir.join(percent_decode(url)))
+}
+
+// Imports a markdown file as a new page below `parent_id`, named after the file.
+pub fn import_markdown_file(
+    path: &Path,
+    parent_id: Uuid,
+    attachments: &AttachmentStore,
+    connection: &mut Connection,
+) -> Result<Block, String> {
+    let markdown = match std::fs::read_to_string(path) {
+        Ok(markdown) => markdown,
+        Err(e) => {
+            log::error!("Could not read markdown file {:?}: {:?}", path, e);
+            return Err(String::from("Could not read markdown file"));
+        }
+    };
+
+    let title = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("Imported page"));
+    let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let imported_blocks = parse_markdown(&markdown, &|url| resolve_local_image(attachments, &base_dir, url));
+
+    let transaction = connection.transaction().map_err(|_| String::from("Could not start transaction"))?;
+
+    let page = Block::new(parent_id, String::from("page"), title);
+    if page.save(&transaction).is_err() {
+        return Err(String::from("Could not save imported page"));
+    }
+    save_imported_blocks(page.id, imported_blocks, &transaction)?;
+
+    if let Err(e) = transaction.commit() {
+        log::error!("Could not commit markdown import: {:?}", e);
+        return Err(String::from("Could not import markdown file"));
+    }
+
+    Block::load_by_id(page.id, connection)
+        .ok()
+        .flatten()
+        .ok_or_else(|| String::from("Could not import markdown file"))
+}
+
+#[tauri::command]
+pub fn import_markdown_command(
+    path: String,
+    parent_id: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running import_markdown_command for {}", path);
+
+    let parent_uuid = Uuid::parse_str(&parent_id).map_err(|_| String::from("Invalid parent id"))?;
+    let attachments = AttachmentStore::for_configuration(&configuration.read().unwrap());
+
+    let mut connection = db.get()?;
+    let page = import_markdown_file(Path::new(&path), parent_uuid, &attachments, &mut connection)?;
+
+    emit_block_event(&app, BlockEvent::Created, vec![page.id], vec![page.parent_id]);
+
+    Ok(serde_json::to_string(&page).unwrap())
+}
@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+use uuid::Uuid;
+
+use crate::attachments::AttachmentStore;
+use crate::blocks::{Block, BlockContent};
+
+pub mod markdown_export;
+pub mod markdown_import;
+pub mod notion;
+pub mod obsidian;
+pub mod opml;
+pub mod site;
+pub mod workspace_json;
+
+// A block parsed from an external format, not yet stored.
+#[derive(Debug)]
+pub struct ImportedBlock {
+    pub content: BlockContent,
+    pub favorite: bool,
+    pub children: Vec<ImportedBlock>,
+}
+
+impl ImportedBlock {
+    pub fn new(content: BlockContent) -> Self {
+        ImportedBlock {
+            content,
+            favorite: false,
+            children: Vec::new(),
+        }
+    }
+}
+
+// Stores the imported blocks below `parent_id`, keeping their order and nesting.
+pub fn save_imported_blocks(parent_id: Uuid, imported_blocks: Vec<ImportedBlock>, connection: &Connection) -> Result<(), String> {
+    for imported_block in imported_blocks {
+        let mut block = Block::new(parent_id, imported_block.content.content_type.clone(), String::new());
+        block.block_contents = imported_block.content;
+        block.favorite = imported_block.favorite;
+
+        if block.save(connection).is_err() {
+            return Err(String::from("Could not save imported block"));
+        }
+
+        save_imported_blocks(block.id, imported_block.children, connection)?;
+    }
+
+    Ok(())
+}
+
+// Decodes `%XX` escapes, as found in links to local files.
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+pub fn is_remote_url(url: &str) -> bool {
+    url.contains("://") || url.starts_with("data:")
+}
+
+// Copies a local file referenced by an import into the attachment store. When that fails the
+// reference falls back to the file's path, so the block still points somewhere useful.
+pub fn store_local_file(attachments: &AttachmentStore, path: &Path) -> String {
+    match attachments.store_file(path) {
+        Ok(reference) => reference,
+        Err(_) => path.to_string_lossy().to_string(),
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy().starts_with('.'))
+        .unwrap_or(false)
+}
+
+// Directory entries without hidden ones like `.obsidian` or `.trash`, sorted by name.
+pub fn visible_entries(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        log::error!("Could not read import directory {:?}: {:?}", dir, e);
+        String::from("Could not read import directory")
+    })?;
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| !is_hidden(path))
+        .collect();
+    paths.sort();
+
+    Ok(paths)
+}
@@ -0,0 +1,386 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use rusqlite::Connection;
+use serde::Serialize;
+use serde_json::json;
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+use zip::ZipArchive;
+
+use super::markdown_import::parse_markdown;
+use super::{is_remote_url, percent_decode, save_imported_blocks, visible_entries, ImportedBlock};
+use crate::attachments::AttachmentStore;
+use crate::blocks::rich_text::RichText;
+use crate::blocks::Block;
+use crate::configuration::Configuration;
+use crate::events::{emit_block_event, BlockEvent};
+use crate::storage::Database;
+
+#[derive(Debug, Serialize)]
+pub struct NotionImportSummary {
+    pub page_ids: Vec<Uuid>,
+    pub pages: usize,
+    pub tables: usize,
+}
+
+// The export is unpacked into a temporary directory, removed again whatever the outcome.
+struct ExtractedExport {
+    dir: PathBuf,
+}
+
+impl Drop for ExtractedExport {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            log::warn!("Could not remove extracted export {:?}: {:?}", &self.dir, e);
+        }
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .map(|path_extension| path_extension.eq_ignore_ascii_case(extension))
+        .unwrap_or(false)
+}
+
+fn extract_zip(zip_path: &Path, dir: &Path) -> Result<(), String> {
+    let file = File::open(zip_path).map_err(|e| {
+        log::error!("Could not open {:?}: {:?}", zip_path, e);
+        String::from("Could not open Notion export")
+    })?;
+
+    let mut archive = ZipArchive::new(file).map_err(|e| {
+        log::error!("Could not read zip {:?}: {:?}", zip_path, e);
+        String::from("Notion export is not a valid zip file")
+    })?;
+
+    archive.extract(dir).map_err(|e| {
+        log::error!("Could not extract {:?}: {:?}", zip_path, e);
+        String::from("Could not extract Notion export")
+    })
+}
+
+fn extract_export(zip_path: &Path) -> Result<ExtractedExport, String> {
+    let extracted = ExtractedExport {
+        dir: std::env::temp_dir().join(format!("my-blocks-notion-{}", Uuid::now_v7())),
+    };
+    extract_zip(zip_path, &extracted.dir)?;
+
+    // Large workspaces are exported as a zip holding one zip per part.
+    for path in visible_entries(&extracted.dir)? {
+        if path.is_file() && has_extension(&path, "zip") {
+            extract_zip(&path, &extracted.dir)?;
+            std::fs::remove_file(&path).map_err(|_| String::from("Could not extract Notion export"))?;
+        }
+    }
+
+    Ok(extracted)
+}
+
+// Notion appends the page id to every exported name, e.g. `Roadmap 3f2a0c1e9b8d4f6a8c7e5d4b3a2f1e0d`.
+fn strip_notion_id(name: &str) -> (&str, Option<&str>) {
+    if let Some((title, id)) = name.rsplit_once(' ') {
+        let hex = id.replace('-', "");
+        if hex.len() == 32 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return (title.trim_end(), Some(id));
+        }
+    }
+
+    (name, None)
+}
+
+fn entry_title(path: &Path) -> (String, Option<String>) {
+    let name = if has_extension(path, "md") || has_extension(path, "csv") {
+        path.file_stem()
+    } else {
+        path.file_name()
+    };
+    let name = name.map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let (title, notion_id) = strip_notion_id(&name);
+
+    let title = if title.is_empty() { String::from("Untitled") } else { title.to_string() };
+    (title, notion_id.map(String::from))
+}
+
+// The database CSV to import for `path`. Recent exports write both `Name.csv` with the current view
+// and `Name_all.csv` with every row, the latter is used and the former skipped.
+fn database_csv(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let stem = path.file_stem()?.to_string_lossy().to_string();
+
+    match stem.strip_suffix("_all") {
+        Some(base) => Some((path.to_path_buf(), path.with_file_name(base))),
+        None => {
+            let all = path.with_file_name(format!("{}_all.csv", stem));
+            if all.exists() {
+                return None;
+            }
+            Some((path.to_path_buf(), path.with_file_name(stem)))
+        }
+    }
+}
+
+fn read_csv(path: &Path) -> Result<(Vec<String>, Vec<Vec<String>>), String> {
+    let csv_error = |e: csv::Error| {
+        log::error!("Could not read database {:?}: {:?}", path, e);
+        String::from("Could not read Notion database")
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(path)
+        .map_err(csv_error)?;
+
+    let columns: Vec<String> = reader
+        .headers()
+        .map_err(csv_error)?
+        .iter()
+        .map(|column| column.trim_start_matches('\u{feff}').to_string())
+        .collect();
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        rows.push(record.map_err(csv_error)?.iter().map(String::from).collect());
+    }
+
+    Ok((columns, rows))
+}
+
+struct NotionPage {
+    page_id: Uuid,
+    title: String,
+    path: PathBuf,
+}
+
+struct NotionImport<'a> {
+    connection: &'a Connection,
+    parent_id: Uuid,
+    // Blocks created directly below the chosen parent.
+    top_level_ids: Vec<Uuid>,
+    // Every imported page and database by its canonical path, for relative links.
+    blocks_by_path: HashMap<PathBuf, Uuid>,
+    pages: Vec<NotionPage>,
+    tables: usize,
+}
+
+impl<'a> NotionImport<'a> {
+    fn remember_path(&mut self, path: &Path, block_id: Uuid) {
+        if let Ok(path) = std::fs::canonicalize(path) {
+            self.blocks_by_path.insert(path, block_id);
+        }
+    }
+
+    fn save_block(&mut self, block: &Block) -> Result<(), String> {
+        block.save(self.connection).map_err(|_| String::from("Could not save imported block"))?;
+
+        if block.parent_id == self.parent_id {
+            self.top_level_ids.push(block.id);
+        }
+        Ok(())
+    }
+
+    fn import_page(&mut self, path: &Path, parent_id: Uuid) -> Result<Uuid, String> {
+        let (title, notion_id) = entry_title(path);
+
+        let mut page = Block::new(parent_id, String::from("page"), title.clone());
+        if let Some(notion_id) = notion_id {
+            page.block_contents.properties.insert(String::from("notion_id"), json!(notion_id));
+        }
+        self.save_block(&page)?;
+
+        self.remember_path(path, page.id);
+        if has_extension(path, "md") {
+            self.pages.push(NotionPage {
+                page_id: page.id,
+                title,
+                path: path.to_path_buf(),
+            });
+        }
+
+        Ok(page.id)
+    }
+
+    fn import_database(&mut self, csv_path: &Path, rows_dir: &Path, parent_id: Uuid) -> Result<(), String> {
+        let (title, notion_id) = entry_title(rows_dir);
+        let (columns, rows) = read_csv(csv_path)?;
+
+        let mut table = Block::new(parent_id, String::from("table"), title);
+        let properties = &mut table.block_contents.properties;
+        properties.insert(String::from("columns"), json!(columns));
+        properties.insert(String::from("rows"), json!(rows));
+        if let Some(notion_id) = notion_id {
+            properties.insert(String::from("notion_id"), json!(notion_id));
+        }
+        self.save_block(&table)?;
+
+        self.remember_path(csv_path, table.id);
+        self.tables += 1;
+
+        // Every row is also exported as a page, those become children of the table in row order.
+        if rows_dir.is_dir() {
+            let row_titles: Vec<String> = rows.iter().filter_map(|row| row.first().cloned()).collect();
+            self.import_directory(rows_dir, table.id, &row_titles)?;
+        }
+
+        Ok(())
+    }
+
+    // Imports the pages, databases and folders of `dir` below `parent_id`. Pages listed in `order`
+    // come first, in that order.
+    fn import_directory(&mut self, dir: &Path, parent_id: Uuid, order: &[String]) -> Result<(), String> {
+        let mut entries = visible_entries(dir)?;
+        let position = |path: &PathBuf| {
+            let (title, _) = entry_title(path);
+            order.iter().position(|ordered| *ordered == title).unwrap_or(order.len())
+        };
+        entries.sort_by_key(|path| position(path));
+
+        // A page's sub pages are in a folder named like the page itself.
+        let mut handled_dirs: HashSet<PathBuf> = HashSet::new();
+
+        for path in entries.iter().filter(|path| path.is_file()) {
+            if has_extension(path, "md") {
+                let page_id = self.import_page(path, parent_id)?;
+                let children_dir = path.with_extension("");
+                if children_dir.is_dir() {
+                    self.import_directory(&children_dir, page_id, &[])?;
+                    handled_dirs.insert(children_dir);
+                }
+            } else if has_extension(path, "csv") {
+                if let Some((csv_path, rows_dir)) = database_csv(path) {
+                    self.import_database(&csv_path, &rows_dir, parent_id)?;
+                    handled_dirs.insert(rows_dir);
+                }
+            }
+        }
+
+        for path in entries.iter().filter(|path| path.is_dir() && !handled_dirs.contains(*path)) {
+            if contains_pages(path) {
+                let page_id = self.import_page(path, parent_id)?;
+                self.import_directory(path, page_id, &[])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resolve_link(&self, page_dir: &Path, link: &str) -> Option<Uuid> {
+        if is_remote_url(link) || link.starts_with('#') || link.starts_with("page:") {
+            return None;
+        }
+
+        let path = percent_decode(link.split('#').next().unwrap_or_default());
+        let path = std::fs::canonicalize(page_dir.join(path)).ok()?;
+        self.blocks_by_path.get(&path).copied()
+    }
+
+    fn import_page_contents(&self, page: &NotionPage, attachments: &AttachmentStore) -> Result<(), String> {
+        let markdown = std::fs::read_to_string(&page.path).map_err(|e| {
+            log::error!("Could not read page {:?}: {:?}", &page.path, e);
+            String::from("Could not read Notion page")
+        })?;
+        let page_dir = page.path.parent().unwrap_or(Path::new(""));
+
+        let resolve_image = |url: &str| {
+            if is_remote_url(url) {
+                return url.to_string();
+            }
+            // The export is extracted into a temporary directory, a path into it would not outlive the import.
+            attachments
+                .store_file(&page_dir.join(percent_decode(url)))
+                .unwrap_or_else(|_| url.to_string())
+        };
+        let mut imported_blocks = parse_markdown(&markdown, &resolve_image);
+
+        // The page title is repeated as the first heading.
+        if let Some(first) = imported_blocks.first() {
+            if first.content.content_type == "heading" && first.content.plain_text() == page.title {
+                imported_blocks.remove(0);
+            }
+        }
+
+        link_pages(&mut imported_blocks, &|link| self.resolve_link(page_dir, link));
+        save_imported_blocks(page.page_id, imported_blocks, self.connection)
+    }
+}
+
+fn contains_pages(dir: &Path) -> bool {
+    visible_entries(dir)
+        .unwrap_or_default()
+        .iter()
+        .any(|path| if path.is_dir() { contains_pages(path) } else { has_extension(path, "md") || has_extension(path, "csv") })
+}
+
+// Turns links to exported files into page references.
+fn link_pages(blocks: &mut [ImportedBlock], resolve_link: &dyn Fn(&str) -> Option<Uuid>) {
+    for block in blocks.iter_mut() {
+        if let Some(rich_text) = &block.content.rich_text {
+            let spans = rich_text
+                .spans
+                .iter()
+                .cloned()
+                .map(|mut span| {
+                    if let Some(page_id) = span.link.as_deref().and_then(resolve_link) {
+                        span.link = None;
+                        span.page_reference = Some(page_id);
+                    }
+                    span
+                })
+                .collect();
+            block.content.set_rich_text(RichText::from_spans(spans));
+        }
+
+        link_pages(&mut block.children, resolve_link);
+    }
+}
+
+#[tauri::command]
+pub fn import_notion_export_command(
+    path: String,
+    parent_id: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running import_notion_export_command for {}", path);
+
+    let parent_uuid = Uuid::parse_str(&parent_id).map_err(|_| String::from("Invalid parent id"))?;
+    let extracted = extract_export(Path::new(&path))?;
+
+    let mut connection = db.get()?;
+    let transaction = connection.transaction().map_err(|_| String::from("Could not start transaction"))?;
+
+    let mut import = NotionImport {
+        connection: &transaction,
+        parent_id: parent_uuid,
+        top_level_ids: Vec::new(),
+        blocks_by_path: HashMap::new(),
+        pages: Vec::new(),
+        tables: 0,
+    };
+
+    // Every page exists before any content is imported, so links can point forward.
+    import.import_directory(&extracted.dir, parent_uuid, &[])?;
+
+    let attachments = AttachmentStore::for_configuration(&configuration.read().unwrap());
+    for page in &import.pages {
+        import.import_page_contents(page, &attachments)?;
+    }
+
+    let summary = NotionImportSummary {
+        page_ids: import.top_level_ids.clone(),
+        pages: import.pages.len(),
+        tables: import.tables,
+    };
+
+    if let Err(e) = transaction.commit() {
+        log::error!("Could not commit Notion import: {:?}", e);
+        return Err(String::from("Could not import Notion export"));
+    }
+
+    log::debug!("Imported {} pages and {} tables from Notion", summary.pages, summary.tables);
+    emit_block_event(&app, BlockEvent::Created, summary.page_ids.clone(), vec![parent_uuid]);
+
+    Ok(serde_json::to_string(&summary).unwrap())
+}
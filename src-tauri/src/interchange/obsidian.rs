@@ -0,0 +1,492 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use rusqlite::Connection;
+use serde::Serialize;
+use serde_json::json;
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+use super::markdown_import::parse_markdown;
+use super::{is_remote_url, percent_decode, save_imported_blocks, store_local_file, visible_entries};
+use crate::attachments::AttachmentStore;
+use crate::blocks::rich_text::RichText;
+use crate::blocks::Block;
+use crate::configuration::Configuration;
+use crate::events::{emit_block_event, BlockEvent};
+use crate::storage::Database;
+
+// Imported pages remember where they came from, so importing the vault again updates them.
+const VAULT_PROPERTY: &str = "obsidian_vault";
+const PATH_PROPERTY: &str = "obsidian_path";
+
+#[derive(Debug, Serialize)]
+pub struct VaultImportSummary {
+    pub page_id: Uuid,
+    pub notes: usize,
+    pub created: usize,
+    pub updated: usize,
+}
+
+struct VaultNote {
+    page_id: Uuid,
+    path: PathBuf,
+    body: String,
+}
+
+// How wikilinks and embeds find their targets. Keys are lowercase, as Obsidian matches them.
+#[derive(Default)]
+struct VaultIndex {
+    // Path relative to the vault, without the `.md` extension.
+    notes_by_path: HashMap<String, Uuid>,
+    // File name without extension, and aliases from the front matter.
+    notes_by_name: HashMap<String, Uuid>,
+    // Every other file, by file name, for embeds.
+    files_by_name: HashMap<String, PathBuf>,
+}
+
+impl VaultIndex {
+    fn resolve_note(&self, target: &str) -> Option<Uuid> {
+        let target = target.trim_end_matches(".md").to_lowercase();
+
+        self.notes_by_path
+            .get(&target)
+            .or_else(|| self.notes_by_name.get(&target))
+            .copied()
+    }
+}
+
+struct VaultImport<'a> {
+    vault: PathBuf,
+    vault_key: String,
+    connection: &'a Connection,
+    index: VaultIndex,
+    notes: Vec<VaultNote>,
+    created: usize,
+    updated: usize,
+}
+
+fn is_note(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.eq_ignore_ascii_case("md"))
+        .unwrap_or(false)
+}
+
+// Symlinked folders can lead back into a folder already walked, every folder is entered once.
+fn first_visit(dir: &Path, visited: &mut HashSet<PathBuf>) -> bool {
+    match std::fs::canonicalize(dir) {
+        Ok(dir) => visited.insert(dir),
+        Err(_) => false,
+    }
+}
+
+fn contains_notes(dir: &Path, visited: &mut HashSet<PathBuf>) -> bool {
+    visible_entries(dir)
+        .unwrap_or_default()
+        .iter()
+        .any(|path| if path.is_dir() { first_visit(path, visited) && contains_notes(path, visited) } else { is_note(path) })
+}
+
+fn file_title(path: &Path) -> String {
+    path.file_stem()
+        .or_else(|| path.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("Untitled"))
+}
+
+// Splits a leading `---` delimited YAML block from the note body.
+fn split_front_matter(markdown: &str) -> (Option<&str>, &str) {
+    let rest = match markdown.strip_prefix("---\n").or_else(|| markdown.strip_prefix("---\r\n")) {
+        Some(rest) => rest,
+        None => return (None, markdown),
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+
+    (None, markdown)
+}
+
+fn front_matter_properties(yaml: &str, path: &Path) -> BTreeMap<String, serde_json::Value> {
+    match serde_yaml::from_str::<serde_json::Value>(yaml) {
+        Ok(serde_json::Value::Object(fields)) => fields.into_iter().collect(),
+        Ok(serde_json::Value::Null) => BTreeMap::new(),
+        Ok(_) => {
+            log::warn!("Ignoring front matter of {:?}, it is not a mapping", path);
+            BTreeMap::new()
+        }
+        Err(e) => {
+            log::warn!("Ignoring invalid front matter of {:?}: {:?}", path, e);
+            BTreeMap::new()
+        }
+    }
+}
+
+fn aliases(properties: &BTreeMap<String, serde_json::Value>) -> Vec<String> {
+    match properties.get("aliases").or_else(|| properties.get("alias")) {
+        Some(serde_json::Value::String(alias)) => vec![alias.clone()],
+        Some(serde_json::Value::Array(aliases)) => aliases
+            .iter()
+            .filter_map(|alias| alias.as_str().map(String::from))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// The Markdown for a single `[[...]]`, or for `![[...]]` when `embed` is set.
+fn wikilink_markdown(inner: &str, embed: bool, resolve_note: &dyn Fn(&str) -> Option<Uuid>) -> String {
+    let (target, alias) = match inner.split_once('|') {
+        Some((target, alias)) => (target.trim(), Some(alias.trim())),
+        None => (inner.trim(), None),
+    };
+    let note = target.split('#').next().unwrap_or_default().trim();
+
+    if embed && !note.is_empty() && !is_note(Path::new(note)) && Path::new(note).extension().is_some() {
+        // The alias of an embedded file is its display size, not a caption.
+        let name = Path::new(note).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        return format!("![{}](<{}>)", RichText::plain(&name).to_markdown(), note);
+    }
+
+    let label = match alias {
+        Some(alias) if !alias.is_empty() => alias.to_string(),
+        _ => target.replace('#', " > "),
+    };
+    let label = RichText::plain(&label).to_markdown();
+
+    match resolve_note(note) {
+        Some(page_id) if !note.is_empty() => format!("[{}](page:{})", label, page_id),
+        _ => label,
+    }
+}
+
+fn rewrite_line(line: &str, resolve_note: &dyn Fn(&str) -> Option<Uuid>) -> String {
+    let mut rewritten = String::with_capacity(line.len());
+    let mut rest = line;
+
+    loop {
+        let next_code = rest.find('`');
+        let next_link = rest.find("[[");
+
+        match (next_code, next_link) {
+            // Code spans are copied as they are.
+            (Some(code), link) if link.is_none_or(|link| code < link) => {
+                let run = rest[code..].chars().take_while(|&c| c == '`').count();
+                let end = match rest[code + run..].find(&rest[code..code + run]) {
+                    Some(close) => code + run + close + run,
+                    None => code + run,
+                };
+                rewritten.push_str(&rest[..end]);
+                rest = &rest[end..];
+            }
+            (_, Some(link)) => {
+                let close = match rest[link + 2..].find("]]") {
+                    Some(close) => link + 2 + close,
+                    None => break,
+                };
+                let embed = link > 0 && rest.as_bytes()[link - 1] == b'!';
+
+                rewritten.push_str(&rest[..if embed { link - 1 } else { link }]);
+                rewritten.push_str(&wikilink_markdown(&rest[link + 2..close], embed, resolve_note));
+                rest = &rest[close + 2..];
+            }
+            _ => break,
+        }
+    }
+
+    rewritten.push_str(rest);
+    rewritten
+}
+
+// Turns wikilinks into Markdown links to `page:<id>`. Unresolved links keep only their label, code
+// is left untouched.
+fn rewrite_wikilinks(markdown: &str, resolve_note: &dyn Fn(&str) -> Option<Uuid>) -> String {
+    let mut rewritten = String::with_capacity(markdown.len());
+    let mut fence: Option<&str> = None;
+
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+
+        match fence {
+            Some(marker) => {
+                if trimmed.starts_with(marker) {
+                    fence = None;
+                }
+                rewritten.push_str(line);
+            }
+            None if trimmed.starts_with("```") || trimmed.starts_with("~~~") => {
+                fence = Some(&trimmed[..3]);
+                rewritten.push_str(line);
+            }
+            None => rewritten.push_str(&rewrite_line(line, resolve_note)),
+        }
+    }
+
+    rewritten
+}
+
+impl<'a> VaultImport<'a> {
+    fn relative_path(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.vault).unwrap_or(path);
+
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+
+    fn find_imported_page(&self, relative_path: &str) -> Result<Option<Block>, String> {
+        let page_id: Option<String> = self
+            .connection
+            .query_row(
+                "SELECT id FROM blocks WHERE block_type = 'page' AND json_extract(data, '$.properties.obsidian_vault') = ?1 AND json_extract(data, '$.properties.obsidian_path') = ?2",
+                rusqlite::params![&self.vault_key, relative_path],
+                |row| row.get(0),
+            )
+            .map(Some)
+            .or_else(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                e => Err(e),
+            })
+            .map_err(|e| {
+                log::error!("Could not look up imported page {}: {:?}", relative_path, e);
+                String::from("Could not look up imported page")
+            })?;
+
+        match page_id.and_then(|id| Uuid::parse_str(&id).ok()) {
+            Some(page_id) => Block::load_by_id(page_id, self.connection).map_err(|_| String::from("Could not load imported page")),
+            None => Ok(None),
+        }
+    }
+
+    // Creates the page for a vault file or folder, or updates the one from an earlier import.
+    fn sync_page(
+        &mut self,
+        parent_id: Uuid,
+        title: String,
+        relative_path: &str,
+        mut properties: BTreeMap<String, serde_json::Value>,
+    ) -> Result<Uuid, String> {
+        properties.insert(String::from(VAULT_PROPERTY), json!(self.vault_key));
+        properties.insert(String::from(PATH_PROPERTY), json!(relative_path));
+
+        let page = match self.find_imported_page(relative_path)? {
+            Some(mut page) => {
+                page.parent_id = parent_id;
+                page.block_contents.contents = Some(title);
+                page.block_contents.properties = properties;
+                self.updated += 1;
+                page
+            }
+            None => {
+                let mut page = Block::new(parent_id, String::from("page"), title);
+                page.block_contents.properties = properties;
+                self.created += 1;
+                page
+            }
+        };
+
+        if page.save(self.connection).is_err() {
+            return Err(String::from("Could not save imported page"));
+        }
+
+        Ok(page.id)
+    }
+
+    fn index_files(&mut self, dir: &Path, visited: &mut HashSet<PathBuf>) -> Result<(), String> {
+        for path in visible_entries(dir)? {
+            if path.is_dir() {
+                if first_visit(&path, visited) {
+                    self.index_files(&path, visited)?;
+                }
+            } else if !is_note(&path) {
+                if let Some(name) = path.file_name() {
+                    let name = name.to_string_lossy().to_lowercase();
+                    self.index.files_by_name.entry(name).or_insert(path);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn import_folder(&mut self, dir: &Path, parent_id: Uuid, visited: &mut HashSet<PathBuf>) -> Result<(), String> {
+        for path in visible_entries(dir)? {
+            let relative_path = self.relative_path(&path);
+
+            if path.is_dir() {
+                // Folders holding only attachments do not become pages.
+                if first_visit(&path, visited) && contains_notes(&path, &mut visited.clone()) {
+                    let page_id = self.sync_page(parent_id, file_title(&path), &relative_path, BTreeMap::new())?;
+                    self.import_folder(&path, page_id, visited)?;
+                }
+                continue;
+            }
+
+            if !is_note(&path) {
+                continue;
+            }
+
+            let markdown = std::fs::read_to_string(&path).map_err(|e| {
+                log::error!("Could not read note {:?}: {:?}", &path, e);
+                String::from("Could not read note")
+            })?;
+            let (front_matter, body) = split_front_matter(&markdown);
+            let properties = front_matter
+                .map(|yaml| front_matter_properties(yaml, &path))
+                .unwrap_or_default();
+            let note_aliases = aliases(&properties);
+
+            let page_id = self.sync_page(parent_id, file_title(&path), &relative_path, properties)?;
+
+            self.index
+                .notes_by_path
+                .insert(relative_path.trim_end_matches(".md").to_lowercase(), page_id);
+            for name in std::iter::once(file_title(&path)).chain(note_aliases) {
+                self.index.notes_by_name.entry(name.to_lowercase()).or_insert(page_id);
+            }
+
+            self.notes.push(VaultNote {
+                page_id,
+                path: path.clone(),
+                body: body.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn resolve_image(&self, attachments: &AttachmentStore, note_dir: &Path, url: &str) -> String {
+        if is_remote_url(url) {
+            return url.to_string();
+        }
+
+        // Obsidian finds embeds next to the note, from the vault root, or by file name anywhere.
+        let decoded = percent_decode(url);
+        let by_name = Path::new(&decoded)
+            .file_name()
+            .and_then(|name| self.index.files_by_name.get(&name.to_string_lossy().to_lowercase()));
+
+        let found = [note_dir.join(&decoded), self.vault.join(&decoded)]
+            .into_iter()
+            .find(|candidate| candidate.is_file())
+            .or_else(|| by_name.cloned());
+
+        match found {
+            Some(path) => store_local_file(attachments, &path),
+            None => {
+                log::warn!("Could not find embedded file {}", url);
+                url.to_string()
+            }
+        }
+    }
+
+    // Returns the blocks of an earlier import it deleted.
+    fn import_note(&self, note: &VaultNote, attachments: &AttachmentStore) -> Result<Vec<Uuid>, String> {
+        // The blocks of an earlier import are replaced, pages nested below the note stay.
+        let previous_blocks: Vec<String> = self
+            .connection
+            .prepare("SELECT id FROM blocks WHERE parent_id = ?1 AND block_type != 'page'")
+            .and_then(|mut statement| {
+                statement
+                    .query_map(rusqlite::params![note.page_id.to_string()], |row| row.get(0))?
+                    .collect()
+            })
+            .map_err(|e| {
+                log::error!("Could not look up the blocks of note {:?}: {:?}", &note.path, e);
+                String::from("Could not replace imported note")
+            })?;
+        let previous_blocks: Vec<Uuid> = previous_blocks.iter().filter_map(|block_id| Uuid::parse_str(block_id).ok()).collect();
+        for block_id in &previous_blocks {
+            Block::delete_with_descendants(*block_id, self.connection).map_err(|e| {
+                log::error!("Could not delete block {} of note {:?}: {}", block_id, &note.path, e);
+                String::from("Could not replace imported note")
+            })?;
+        }
+
+        let markdown = rewrite_wikilinks(&note.body, &|target| self.index.resolve_note(target));
+        let note_dir = note.path.parent().unwrap_or(&self.vault);
+        let imported_blocks = parse_markdown(&markdown, &|url| self.resolve_image(attachments, note_dir, url));
+
+        save_imported_blocks(note.page_id, imported_blocks, self.connection)?;
+
+        Ok(previous_blocks)
+    }
+}
+
+#[tauri::command]
+pub fn import_obsidian_vault_command(
+    path: String,
+    parent_id: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running import_obsidian_vault_command for {}", path);
+
+    let parent_uuid = Uuid::parse_str(&parent_id).map_err(|_| String::from("Invalid parent id"))?;
+    let vault = std::fs::canonicalize(&path).map_err(|e| {
+        log::error!("Could not open vault {:?}: {:?}", &path, e);
+        String::from("Could not open vault")
+    })?;
+    if !vault.is_dir() {
+        return Err(String::from("Vault is not a directory"));
+    }
+
+    let mut connection = db.get()?;
+    let transaction = connection.transaction().map_err(|_| String::from("Could not start transaction"))?;
+
+    let mut import = VaultImport {
+        vault_key: vault.to_string_lossy().to_string(),
+        vault: vault.clone(),
+        connection: &transaction,
+        index: VaultIndex::default(),
+        notes: Vec::new(),
+        created: 0,
+        updated: 0,
+    };
+
+    // Every page exists before any note is converted, so wikilinks can point forward.
+    let root_id = import.sync_page(parent_uuid, file_title(&vault), "", BTreeMap::new())?;
+    let root_created = import.created == 1;
+    import.index_files(&vault, &mut HashSet::from([vault.clone()]))?;
+    import.import_folder(&vault, root_id, &mut HashSet::from([vault.clone()]))?;
+
+    let attachments = AttachmentStore::for_configuration(&configuration.read().unwrap());
+    let mut deleted = Vec::new();
+    let mut deleted_from = Vec::new();
+    for note in &import.notes {
+        let previous_blocks = import.import_note(note, &attachments)?;
+        if !previous_blocks.is_empty() {
+            deleted.extend(previous_blocks);
+            deleted_from.push(note.page_id);
+        }
+    }
+
+    let summary = VaultImportSummary {
+        page_id: root_id,
+        notes: import.notes.len(),
+        created: import.created,
+        updated: import.updated,
+    };
+
+    if let Err(e) = transaction.commit() {
+        log::error!("Could not commit vault import: {:?}", e);
+        return Err(String::from("Could not import vault"));
+    }
+
+    log::debug!("Imported {} notes, {} pages created, {} updated", summary.notes, summary.created, summary.updated);
+    let event = if root_created { BlockEvent::Created } else { BlockEvent::Updated };
+    emit_block_event(&app, event, vec![root_id], vec![parent_uuid]);
+    if !deleted.is_empty() {
+        emit_block_event(&app, BlockEvent::Deleted, deleted, deleted_from);
+    }
+
+    Ok(serde_json::to_string(&summary).unwrap())
+}
@@ -0,0 +1,343 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use quick_xml::events::attributes::Attribute;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::name::QName;
+use quick_xml::{Reader, Writer};
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+use super::markdown_export::page_title;
+use super::{save_imported_blocks, ImportedBlock};
+use crate::blocks::rich_text::RichText;
+use crate::blocks::{Block, BlockContent};
+use crate::events::{emit_block_event, BlockEvent};
+use crate::storage::Database;
+
+#[cfg(test)]
+mod tests;
+
+// Besides the standard `text`, outlines carry what OPML has no place for, so importing an export
+// gives back the same blocks. `<head>` carries the same attributes for the exported page itself.
+const BLOCK_TYPE_ATTRIBUTE: &str = "blockType";
+const CONTENT_TYPE_ATTRIBUTE: &str = "contentType";
+const FAVORITE_ATTRIBUTE: &str = "favorite";
+const MARKDOWN_ATTRIBUTE: &str = "markdown";
+const PROPERTIES_ATTRIBUTE: &str = "properties";
+const TITLE_ATTRIBUTE: &str = "title";
+// Notes as written by other outliners.
+const NOTE_ATTRIBUTE: &str = "_note";
+
+fn xml_error(e: impl std::fmt::Debug) -> String {
+    log::error!("Could not write OPML: {:?}", e);
+    String::from("Could not write OPML file")
+}
+
+// Escapes an attribute value, including line breaks which XML readers would otherwise turn into spaces.
+fn attribute<'a>(key: &'a str, value: &str) -> Attribute<'a> {
+    let escaped = quick_xml::escape::escape(value)
+        .replace('\n', "&#10;")
+        .replace('\r', "&#13;")
+        .replace('\t', "&#9;");
+
+    Attribute {
+        key: QName(key.as_bytes()),
+        value: Cow::Owned(escaped.into_bytes()),
+    }
+}
+
+fn outline_attributes(block: &Block) -> Vec<(&'static str, String)> {
+    let content = &block.block_contents;
+    let text = content.plain_text();
+
+    let mut attributes = vec![
+        ("text", text.clone()),
+        (BLOCK_TYPE_ATTRIBUTE, block.block_type.clone()),
+        (CONTENT_TYPE_ATTRIBUTE, content.content_type.clone()),
+    ];
+
+    if block.favorite {
+        attributes.push((FAVORITE_ATTRIBUTE, String::from("true")));
+    }
+    if content.rich_text.is_some() {
+        let markdown = content.to_markdown();
+        if markdown != text {
+            attributes.push((MARKDOWN_ATTRIBUTE, markdown));
+        }
+    }
+    if !content.properties.is_empty() {
+        attributes.push((PROPERTIES_ATTRIBUTE, serde_json::to_string(&content.properties).unwrap()));
+    }
+    if let Some(title) = &content.title {
+        attributes.push((TITLE_ATTRIBUTE, title.clone()));
+    }
+
+    attributes
+}
+
+fn start_with_attributes<'a>(name: &'a str, block: &Block) -> BytesStart<'a> {
+    let attributes = outline_attributes(block);
+    BytesStart::new(name).with_attributes(attributes.iter().map(|(key, value)| attribute(key, value)))
+}
+
+fn write_outline<W: Write>(writer: &mut Writer<W>, block: &Block) -> Result<(), String> {
+    let start = start_with_attributes("outline", block);
+
+    if block.children.is_empty() {
+        return writer.write_event(Event::Empty(start)).map_err(xml_error);
+    }
+
+    writer.write_event(Event::Start(start)).map_err(xml_error)?;
+    for child in &block.children {
+        write_outline(writer, child)?;
+    }
+    writer.write_event(Event::End(BytesEnd::new("outline"))).map_err(xml_error)
+}
+
+fn write_text_element<W: Write>(writer: &mut Writer<W>, name: &str, text: &str) -> Result<(), String> {
+    writer
+        .create_element(name)
+        .write_text_content(BytesText::new(text))
+        .map_err(xml_error)?;
+    Ok(())
+}
+
+pub fn write_opml<W: Write>(page: &Block, writer: W) -> Result<(), String> {
+    let mut writer = Writer::new_with_indent(writer, b' ', 2);
+
+    writer
+        .write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))
+        .map_err(xml_error)?;
+    writer
+        .write_event(Event::Start(BytesStart::new("opml").with_attributes([("version", "2.0")])))
+        .map_err(xml_error)?;
+
+    writer.write_event(Event::Start(start_with_attributes("head", page))).map_err(xml_error)?;
+    write_text_element(&mut writer, "title", &page_title(page))?;
+    write_text_element(&mut writer, "dateCreated", &page.created_at_utc.to_rfc2822())?;
+    writer.write_event(Event::End(BytesEnd::new("head"))).map_err(xml_error)?;
+
+    writer.write_event(Event::Start(BytesStart::new("body"))).map_err(xml_error)?;
+    for child in &page.children {
+        write_outline(&mut writer, child)?;
+    }
+    writer.write_event(Event::End(BytesEnd::new("body"))).map_err(xml_error)?;
+
+    writer.write_event(Event::End(BytesEnd::new("opml"))).map_err(xml_error)?;
+    writer.get_mut().flush().map_err(xml_error)
+}
+
+fn outline_attribute_map(start: &BytesStart) -> Result<HashMap<String, String>, String> {
+    let mut attributes = HashMap::new();
+
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|e| format!("Invalid outline attribute: {}", e))?;
+        let key = String::from_utf8_lossy(attribute.key.as_ref()).to_string();
+        let value = attribute
+            .unescape_value()
+            .map_err(|e| format!("Invalid outline attribute {}: {}", key, e))?;
+        attributes.insert(key, value.to_string());
+    }
+
+    Ok(attributes)
+}
+
+fn imported_block_from_outline(start: &BytesStart) -> Result<ImportedBlock, String> {
+    let attributes = outline_attribute_map(start)?;
+    let attribute = |name: &str| attributes.get(name).map(String::as_str);
+
+    let text = attribute("text").unwrap_or_default();
+    let content_type = match (attribute(CONTENT_TYPE_ATTRIBUTE), attribute(BLOCK_TYPE_ATTRIBUTE)) {
+        (Some(content_type), _) => content_type,
+        (None, Some("page")) => "page",
+        (None, _) => "paragraph",
+    };
+
+    // Outlines from other tools hold plain text, only our own exports are read as Markdown.
+    let mut content = match attribute(MARKDOWN_ATTRIBUTE) {
+        Some(markdown) => BlockContent::from_markdown(content_type.to_string(), markdown),
+        None => {
+            let mut content = BlockContent::new(content_type.to_string(), String::new());
+            match content.holds_rich_text() {
+                true => content.set_rich_text(RichText::plain(text)),
+                false => content.contents = Some(text.to_string()),
+            }
+            content
+        }
+    };
+
+    if let Some(properties) = attribute(PROPERTIES_ATTRIBUTE) {
+        content.properties = serde_json::from_str(properties).map_err(|_| format!("Invalid properties on outline {}", text))?;
+    }
+    content.title = attribute(TITLE_ATTRIBUTE).map(String::from);
+
+    let mut imported_block = ImportedBlock::new(content);
+    imported_block.favorite = attribute(FAVORITE_ATTRIBUTE) == Some("true");
+
+    if let Some(note) = attribute(NOTE_ATTRIBUTE).filter(|note| !note.is_empty()) {
+        let mut note_content = BlockContent::new(String::from("paragraph"), String::new());
+        note_content.set_rich_text(RichText::plain(note));
+        imported_block.children.push(ImportedBlock::new(note_content));
+    }
+
+    Ok(imported_block)
+}
+
+pub struct ParsedOpml {
+    pub title: Option<String>,
+    // The exported page itself, only our own exports describe it.
+    pub page: Option<ImportedBlock>,
+    pub blocks: Vec<ImportedBlock>,
+}
+
+pub fn parse_opml<R: BufRead>(reader: R) -> Result<ParsedOpml, String> {
+    let mut reader = Reader::from_reader(reader);
+    let mut buffer = Vec::new();
+
+    let mut title: Option<String> = None;
+    let mut page: Option<ImportedBlock> = None;
+    let mut in_title = false;
+    // Outlines that are still open, innermost last.
+    let mut open_outlines: Vec<ImportedBlock> = Vec::new();
+    let mut blocks: Vec<ImportedBlock> = Vec::new();
+
+    let attach = |block: ImportedBlock, open_outlines: &mut Vec<ImportedBlock>, blocks: &mut Vec<ImportedBlock>| {
+        match open_outlines.last_mut() {
+            Some(parent) => parent.children.push(block),
+            None => blocks.push(block),
+        }
+    };
+
+    loop {
+        let event = reader.read_event_into(&mut buffer).map_err(|e| {
+            log::error!("Could not parse OPML at {}: {:?}", reader.buffer_position(), e);
+            format!("Invalid OPML file: {}", e)
+        })?;
+
+        match event {
+            Event::Start(start) if start.name().as_ref() == b"outline" => {
+                open_outlines.push(imported_block_from_outline(&start)?);
+            }
+            Event::Empty(start) if start.name().as_ref() == b"outline" => {
+                let block = imported_block_from_outline(&start)?;
+                attach(block, &mut open_outlines, &mut blocks);
+            }
+            Event::End(end) if end.name().as_ref() == b"outline" => {
+                if let Some(block) = open_outlines.pop() {
+                    attach(block, &mut open_outlines, &mut blocks);
+                }
+            }
+            Event::Start(start)
+                if start.name().as_ref() == b"head" && matches!(start.try_get_attribute(BLOCK_TYPE_ATTRIBUTE), Ok(Some(_))) =>
+            {
+                page = Some(imported_block_from_outline(&start)?);
+            }
+            Event::Start(start) if start.name().as_ref() == b"title" && open_outlines.is_empty() => in_title = true,
+            Event::End(end) if end.name().as_ref() == b"title" => in_title = false,
+            Event::Text(text) if in_title => {
+                let text = text.unescape().map_err(|e| format!("Invalid OPML title: {}", e))?;
+                title.get_or_insert_with(String::new).push_str(&text);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+
+        buffer.clear();
+    }
+
+    if !open_outlines.is_empty() {
+        return Err(String::from("Invalid OPML file: unclosed outline"));
+    }
+
+    Ok(ParsedOpml { title, page, blocks })
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpmlExportSummary {
+    pub page_id: Uuid,
+    pub path: PathBuf,
+}
+
+#[tauri::command]
+pub fn export_opml_command(
+    page_id: String,
+    path: String,
+    db: State<Database>,
+) -> Result<String, String> {
+    log::debug!("Running export_opml_command for page {} to {}", page_id, path);
+    let connection = db.get()?;
+
+    let page_uuid = Uuid::parse_str(&page_id).map_err(|_| String::from("Invalid page id"))?;
+    let page = match Block::load_by_id(page_uuid, &connection).map_err(|_| String::from("Could not load page"))? {
+        Some(page) => page,
+        None => return Err(String::from("Page not found")),
+    };
+
+    let path = PathBuf::from(path);
+    let file = File::create(&path).map_err(|e| {
+        log::error!("Could not create {:?}: {:?}", &path, e);
+        String::from("Could not create OPML file")
+    })?;
+    write_opml(&page, BufWriter::new(file))?;
+
+    Ok(serde_json::to_string(&OpmlExportSummary { page_id: page.id, path }).unwrap())
+}
+
+// Imports an OPML outline as a new page below `parent_id`, named after the outline. The page of one
+// of our exports comes back as it was exported.
+pub fn import_opml_file(path: &Path, parent_id: Uuid, connection: &mut Connection) -> Result<Block, String> {
+    let file = File::open(path).map_err(|e| {
+        log::error!("Could not open {:?}: {:?}", path, e);
+        String::from("Could not open OPML file")
+    })?;
+    let opml = parse_opml(BufReader::new(file))?;
+
+    let title = opml.title.filter(|title| !title.trim().is_empty()).unwrap_or_else(|| String::from("Imported outline"));
+
+    let transaction = connection.transaction().map_err(|_| String::from("Could not start transaction"))?;
+
+    let mut page = Block::new(parent_id, String::from("page"), title);
+    if let Some(exported_page) = opml.page {
+        page.block_contents = exported_page.content;
+        page.favorite = exported_page.favorite;
+    }
+    if page.save(&transaction).is_err() {
+        return Err(String::from("Could not save imported page"));
+    }
+    save_imported_blocks(page.id, opml.blocks, &transaction)?;
+
+    if let Err(e) = transaction.commit() {
+        log::error!("Could not commit OPML import: {:?}", e);
+        return Err(String::from("Could not import OPML file"));
+    }
+
+    Block::load_by_id(page.id, connection)
+        .ok()
+        .flatten()
+        .ok_or_else(|| String::from("Could not import OPML file"))
+}
+
+#[tauri::command]
+pub fn import_opml_command(
+    path: String,
+    parent_id: String,
+    db: State<Database>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running import_opml_command for {}", path);
+
+    let parent_uuid = Uuid::parse_str(&parent_id).map_err(|_| String::from("Invalid parent id"))?;
+
+    let mut connection = db.get()?;
+    let page = import_opml_file(Path::new(&path), parent_uuid, &mut connection)?;
+
+    emit_block_event(&app, BlockEvent::Created, vec![page.id], vec![page.parent_id]);
+
+    Ok(serde_json::to_string(&page).unwrap())
+}
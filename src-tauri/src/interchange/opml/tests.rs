@@ -0,0 +1,38 @@
+use uuid::Uuid;
+
+use super::{import_opml_file, write_opml};
+use crate::blocks::repository::{self, BlockRepository};
+use crate::blocks::Block;
+use crate::storage::testing::in_memory_pool;
+
+#[test]
+fn exported_pages_import_as_they_were() {
+    let pool = in_memory_pool();
+    let mut connection = pool.get().unwrap();
+    let repository = repository::for_connection(&connection);
+    let (home_page, _) = Block::find_or_create_home_page(Uuid::now_v7(), &repository).unwrap();
+
+    let mut page = Block::new(home_page.id, String::from("page"), String::from("Trip"));
+    page.favorite = true;
+    page.block_contents.title = Some(String::from("Trip to the coast"));
+    page.block_contents.properties.insert(String::from("icon"), serde_json::json!("map"));
+    repository.insert(&page).unwrap();
+    repository.insert(&Block::new(page.id, String::from("paragraph"), String::from("Pack **boots**"))).unwrap();
+    let page = repository.load(page.id).unwrap();
+    drop(repository);
+
+    let path = std::env::temp_dir().join(format!("my-blocks-opml-{}.opml", Uuid::now_v7()));
+    write_opml(&page, std::fs::File::create(&path).unwrap()).unwrap();
+    let imported = import_opml_file(&path, home_page.id, &mut connection);
+    std::fs::remove_file(&path).unwrap();
+    let imported = imported.unwrap();
+
+    assert_ne!(imported.id, page.id);
+    assert!(imported.favorite);
+    assert_eq!(imported.block_contents.to_json().unwrap(), page.block_contents.to_json().unwrap());
+    assert_eq!(imported.children.len(), 1);
+    assert_eq!(
+        imported.children[0].block_contents.to_json().unwrap(),
+        page.children[0].block_contents.to_json().unwrap()
+    );
+}
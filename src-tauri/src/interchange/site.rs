@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use serde::Serialize;
+use tauri::State;
+use uuid::Uuid;
+
+use super::markdown_export::{child_pages, copy_attachments, page_files, page_title, relative_link, ATTACHMENTS_DIR};
+use crate::attachments::{AttachmentStore, ATTACHMENT_SCHEME};
+use crate::blocks::rich_text::RichText;
+use crate::blocks::{Block, BlockContent};
+use crate::configuration::Configuration;
+use crate::storage::Database;
+
+const STYLE_SHEET: &str = include_str!("site/style.css");
+const SEARCH_SCRIPT: &str = include_str!("site/search.js");
+
+#[derive(Debug, Serialize)]
+pub struct PublishSummary {
+    pub index: PathBuf,
+    pub pages: usize,
+    pub attachments: usize,
+}
+
+#[derive(Serialize)]
+struct SearchEntry {
+    title: String,
+    url: String,
+    text: String,
+}
+
+// What a page needs to link to the rest of the site.
+struct PageContext<'a> {
+    // Directory of the page, relative to the site root.
+    dir: &'a Path,
+    files: &'a HashMap<Uuid, PathBuf>,
+    current_page: Uuid,
+}
+
+impl<'a> PageContext<'a> {
+    fn link_to(&self, file: &Path) -> String {
+        escape_html(&relative_link(self.dir, file))
+    }
+
+    fn root(&self) -> String {
+        "../".repeat(self.dir.components().count())
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Links come from imported notes and can hold any scheme. Only web, mail and relative links are
+// published, anything else could run script in the reader's browser.
+fn safe_url(url: &str) -> Option<String> {
+    let scheme = url
+        .split_once(':')
+        .map(|(scheme, _)| scheme)
+        .filter(|scheme| !scheme.contains(['/', '?', '#']));
+
+    match scheme {
+        None => Some(escape_html(url)),
+        Some(scheme) if ["http", "https", "mailto"].contains(&scheme.to_ascii_lowercase().as_str()) => Some(escape_html(url)),
+        Some(_) => None,
+    }
+}
+
+fn rich_text_html(content: &BlockContent, context: &PageContext) -> String {
+    let rich_text = match &content.rich_text {
+        Some(rich_text) => rich_text.clone(),
+        None => RichText::plain(content.contents.as_deref().unwrap_or_default()),
+    };
+
+    let mut html = String::new();
+    for span in &rich_text.spans {
+        let mut span_html = escape_html(&span.text).replace('\n', "<br>");
+
+        if span.code {
+            span_html = format!("<code>{}</code>", span_html);
+        }
+        if span.bold {
+            span_html = format!("<strong>{}</strong>", span_html);
+        }
+        if span.italic {
+            span_html = format!("<em>{}</em>", span_html);
+        }
+        if span.strikethrough {
+            span_html = format!("<del>{}</del>", span_html);
+        }
+
+        // References to pages outside of the site, and links that are not safe to publish, keep only their text.
+        let href = match span.page_reference {
+            Some(page_id) => context.files.get(&page_id).map(|file| context.link_to(file)),
+            None => span.link.as_deref().and_then(safe_url),
+        };
+        if let Some(href) = href {
+            span_html = format!("<a href=\"{}\">{}</a>", href, span_html);
+        }
+
+        html.push_str(&span_html);
+    }
+
+    html
+}
+
+fn attachment_name(reference: &str) -> Option<&str> {
+    reference.strip_prefix(ATTACHMENT_SCHEME)
+}
+
+fn image_src(url: &str, context: &PageContext) -> String {
+    match attachment_name(url) {
+        Some(name) => context.link_to(&Path::new(ATTACHMENTS_DIR).join(name)),
+        None => safe_url(url).unwrap_or_default(),
+    }
+}
+
+fn table_html(content: &BlockContent) -> String {
+    let cells = |name: &str| -> Vec<serde_json::Value> {
+        content.properties.get(name).and_then(|value| value.as_array()).cloned().unwrap_or_default()
+    };
+    let cell_html = |value: &serde_json::Value| match value {
+        serde_json::Value::String(text) => escape_html(text),
+        serde_json::Value::Null => String::new(),
+        value => escape_html(&value.to_string()),
+    };
+
+    let mut html = String::from("<table>\n<thead><tr>");
+    for column in cells("columns") {
+        html.push_str(&format!("<th>{}</th>", cell_html(&column)));
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for row in cells("rows") {
+        html.push_str("<tr>");
+        for cell in row.as_array().cloned().unwrap_or_default() {
+            html.push_str(&format!("<td>{}</td>", cell_html(&cell)));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>");
+
+    html
+}
+
+// The HTML of a single block, without its children.
+fn block_html(block: &Block, context: &PageContext) -> String {
+    let content = &block.block_contents;
+    let property = |name: &str| content.properties.get(name);
+
+    match content.content_type.as_str() {
+        "page" => {
+            let title = escape_html(&page_title(block));
+            match context.files.get(&block.id) {
+                Some(file) => format!("<p><a href=\"{}\">{}</a></p>", context.link_to(file), title),
+                None => format!("<p>{}</p>", title),
+            }
+        }
+        "heading" => {
+            // The page title is the only `h1`.
+            let level = property("level").and_then(|level| level.as_u64()).unwrap_or(1).clamp(1, 5) + 1;
+            format!("<h{}>{}</h{}>", level, rich_text_html(content, context), level)
+        }
+        "quote" => format!("<blockquote>{}</blockquote>", rich_text_html(content, context)),
+        "code" => {
+            let language = property("language").and_then(|language| language.as_str()).unwrap_or_default();
+            let class = if language.is_empty() { String::new() } else { format!(" class=\"language-{}\"", escape_html(language)) };
+            format!("<pre><code{}>{}</code></pre>", class, escape_html(content.contents.as_deref().unwrap_or_default()))
+        }
+        "image" => {
+            let url = content.contents.as_deref().unwrap_or_default();
+            let alt = property("alt").and_then(|alt| alt.as_str()).unwrap_or_default();
+            let title = match property("title").and_then(|title| title.as_str()) {
+                Some(title) => format!(" title=\"{}\"", escape_html(title)),
+                None => String::new(),
+            };
+            format!("<p><img src=\"{}\" alt=\"{}\"{}></p>", image_src(url, context), escape_html(alt), title)
+        }
+        "table" => format!("<h3>{}</h3>\n{}", escape_html(content.contents.as_deref().unwrap_or_default()), table_html(content)),
+        "todo" => {
+            let checked = property("checked").and_then(|checked| checked.as_bool()).unwrap_or(false);
+            format!(
+                "<input type=\"checkbox\" disabled{}> {}",
+                if checked { " checked" } else { "" },
+                rich_text_html(content, context)
+            )
+        }
+        _ => rich_text_html(content, context),
+    }
+}
+
+fn list_tag(block: &Block) -> Option<&'static str> {
+    let content = &block.block_contents;
+    if block.block_type == "page" {
+        return None;
+    }
+    if content.content_type == "todo" {
+        return Some("ul");
+    }
+
+    match content.properties.get("list").and_then(|list| list.as_str()) {
+        Some("ordered") => Some("ol"),
+        Some(_) => Some("ul"),
+        None => None,
+    }
+}
+
+// Nested blocks are rendered below their parent, except the pages below a page or table: those are
+// pages of their own.
+fn has_inline_children(block: &Block) -> bool {
+    block.block_type != "page" && block.block_contents.content_type != "table" && !block.children.is_empty()
+}
+
+fn blocks_html(blocks: &[Block], context: &PageContext) -> String {
+    let mut html = String::new();
+    let mut open_list: Option<&str> = None;
+
+    for block in blocks {
+        let list = list_tag(block);
+        if open_list != list {
+            if let Some(tag) = open_list {
+                html.push_str(&format!("</{}>\n", tag));
+            }
+            if let Some(tag) = list {
+                html.push_str(&format!("<{}>\n", tag));
+            }
+            open_list = list;
+        }
+
+        let children = if has_inline_children(block) { blocks_html(&block.children, context) } else { String::new() };
+
+        if list.is_some() {
+            html.push_str(&format!("<li>{}\n{}</li>\n", block_html(block, context), children));
+            continue;
+        }
+
+        let block_html = match block.block_contents.content_type.as_str() {
+            "page" | "heading" | "quote" | "code" | "image" | "table" => block_html(block, context),
+            _ => format!("<p>{}</p>", block_html(block, context)),
+        };
+        html.push_str(&block_html);
+        html.push('\n');
+        if !children.is_empty() {
+            html.push_str(&format!("<div class=\"children\">\n{}</div>\n", children));
+        }
+    }
+
+    if let Some(tag) = open_list {
+        html.push_str(&format!("</{}>\n", tag));
+    }
+
+    html
+}
+
+fn navigation_html(page: &Block, context: &PageContext) -> String {
+    let file = match context.files.get(&page.id) {
+        Some(file) => file,
+        None => return String::new(),
+    };
+    let class = if page.id == context.current_page { " class=\"current\"" } else { "" };
+
+    let mut html = format!(
+        "<li><a href=\"{}\"{}>{}</a>",
+        context.link_to(file),
+        class,
+        escape_html(&page_title(page))
+    );
+
+    let sub_pages = child_pages(page);
+    if !sub_pages.is_empty() {
+        html.push_str("\n<ul>\n");
+        for sub_page in sub_pages {
+            html.push_str(&navigation_html(sub_page, context));
+        }
+        html.push_str("</ul>\n");
+    }
+    html.push_str("</li>\n");
+
+    html
+}
+
+fn page_html(page: &Block, site_root: &Block, context: &PageContext) -> String {
+    let root = context.root();
+    let title = escape_html(&page_title(page));
+
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{title}</title>
+<link rel=\"stylesheet\" href=\"{root}style.css\">
+</head>
+<body data-root=\"{root}\">
+<nav>
+<input type=\"search\" id=\"search\" placeholder=\"Search\">
+<ul id=\"search-results\"></ul>
+<ul>
+{navigation}</ul>
+</nav>
+<main>
+<h1>{title}</h1>
+{content}</main>
+<script src=\"{root}search-index.js\"></script>
+<script src=\"{root}search.js\"></script>
+</body>
+</html>
+",
+        title = title,
+        root = root,
+        navigation = navigation_html(site_root, context),
+        content = blocks_html(&page.children, context),
+    )
+}
+
+fn page_text(blocks: &[Block], text: &mut Vec<String>) {
+    for block in blocks {
+        if block.block_type == "page" {
+            continue;
+        }
+        // Image and table contents are a url and a title, not text worth searching.
+        let content = &block.block_contents;
+        if content.holds_rich_text() || content.content_type == "code" {
+            text.push(content.plain_text());
+        }
+        page_text(&block.children, text);
+    }
+}
+
+fn write_file(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            log::error!("Could not create site directory {:?}: {:?}", parent, e);
+            String::from("Could not create site directory")
+        })?;
+    }
+
+    std::fs::write(path, contents).map_err(|e| {
+        log::error!("Could not write {:?}: {:?}", path, e);
+        String::from("Could not write site file")
+    })
+}
+
+// Writes `root` and every page below it as a static site into `out_dir`, the root page as `index.html`.
+pub fn publish_site(root: &Block, out_dir: &Path, attachments: &AttachmentStore) -> Result<PublishSummary, String> {
+    let mut site_files = page_files(root, "html");
+    let index = PathBuf::from("index.html");
+    site_files.files.insert(root.id, index.clone());
+    site_files.pages[0].1 = index.clone();
+
+    let mut search_index = Vec::new();
+    for (page, file) in &site_files.pages {
+        let context = PageContext {
+            dir: file.parent().unwrap_or(Path::new("")),
+            files: &site_files.files,
+            current_page: page.id,
+        };
+        write_file(&out_dir.join(file), page_html(page, root, &context))?;
+
+        let mut text = Vec::new();
+        page_text(&page.children, &mut text);
+        search_index.push(SearchEntry {
+            title: page_title(page),
+            url: relative_link(Path::new(""), file),
+            text: text.join(" "),
+        });
+    }
+
+    let attachment_count = copy_attachments(&site_files.pages, out_dir, attachments)?;
+
+    write_file(&out_dir.join("style.css"), STYLE_SHEET)?;
+    write_file(&out_dir.join("search.js"), SEARCH_SCRIPT)?;
+    // The JSON is for other tools. The pages load the same index as a script, browsers do not fetch
+    // files next to a page opened from disk.
+    let search_index = serde_json::to_string(&search_index).unwrap();
+    write_file(&out_dir.join("search-index.json"), &search_index)?;
+    write_file(&out_dir.join("search-index.js"), format!("window.searchIndex = {};\n", search_index))?;
+
+    Ok(PublishSummary {
+        index: out_dir.join(index),
+        pages: site_files.pages.len(),
+        attachments: attachment_count,
+    })
+}
+
+#[tauri::command]
+pub fn publish_site_command(
+    root_page_id: String,
+    out_dir: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+) -> Result<String, String> {
+    log::debug!("Running publish_site_command for page {} to {}", root_page_id, out_dir);
+    let connection = db.get()?;
+
+    let page_uuid = Uuid::parse_str(&root_page_id).map_err(|_| String::from("Invalid page id"))?;
+    let page = match Block::load_by_id(page_uuid, &connection).map_err(|_| String::from("Could not load page"))? {
+        Some(page) => page,
+        None => return Err(String::from("Page not found")),
+    };
+
+    let attachments = AttachmentStore::for_configuration(&configuration.read().unwrap());
+    let summary = publish_site(&page, Path::new(&out_dir), &attachments)?;
+    log::debug!("Published {} pages and {} attachments", summary.pages, summary.attachments);
+
+    Ok(serde_json::to_string(&summary).unwrap())
+}
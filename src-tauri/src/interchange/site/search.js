@@ -0,0 +1,22 @@
+// Searches the titles and text of every published page, using the index that search-index.js loads before this file.
+const root = document.body.dataset.root;
+const input = document.getElementById('search');
+const results = document.getElementById('search-results');
+const index = window.searchIndex ?? [];
+
+input.addEventListener('input', () => {
+    const query = input.value.trim().toLowerCase();
+    const matches = query === ''
+        ? []
+        : index.filter((page) => `${page.title} ${page.text}`.toLowerCase().includes(query)).slice(0, 20);
+
+    results.replaceChildren(...matches.map((page) => {
+        const link = document.createElement('a');
+        link.href = root + page.url;
+        link.textContent = page.title;
+
+        const item = document.createElement('li');
+        item.append(link);
+        return item;
+    }));
+});
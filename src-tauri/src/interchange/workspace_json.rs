@@ -0,0 +1,368 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, Row};
+use serde::de::{DeserializeSeed, Error as DeError, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+use crate::blocks::BlockContent;
+use crate::configuration::Configuration;
+use crate::events::{emit_app_event, emit_block_event, BlockEvent, WORKSPACE_SWITCHED_EVENT};
+use crate::storage::SCHEMA_VERSION;
+use crate::storage::Database;
+use crate::workspaces::activate_workspace;
+
+const FORMAT_NAME: &str = "my-blocks-workspace";
+// The workspace block and every block below it. UNION stops at blocks that were already visited.
+const WORKSPACE_TREE: &str = "WITH RECURSIVE tree(id) AS (SELECT ?1 UNION SELECT blocks.id FROM blocks JOIN tree ON blocks.parent_id = tree.id)";
+const FORMAT_VERSION: u32 = 1;
+
+// Describes the export. It is written before the `blocks` array, and the importer needs it there
+// to validate the file before touching the database.
+#[derive(Debug, Serialize)]
+struct WorkspaceHeader {
+    format: String,
+    format_version: u32,
+    schema_version: u32,
+    configuration_version: String,
+    workspace_id: Uuid,
+    exported_at_utc: DateTime<Utc>,
+}
+
+// A row of the `blocks` table, kept exactly as stored so a restore reproduces it.
+#[derive(Debug, Deserialize, Serialize)]
+struct BlockRow {
+    id: String,
+    parent_id: String,
+    block_type: String,
+    data: String,
+    block_order: i64,
+    favorite: bool,
+    created_at_utc: String,
+    updated_at_utc: String,
+}
+
+impl BlockRow {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(BlockRow {
+            id: row.get("id")?,
+            parent_id: row.get("parent_id")?,
+            block_type: row.get("block_type")?,
+            data: row.get("data")?,
+            block_order: row.get("block_order")?,
+            favorite: row.get("favorite")?,
+            created_at_utc: row.get("created_at_utc")?,
+            updated_at_utc: row.get("updated_at_utc")?,
+        })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        Uuid::parse_str(&self.id).map_err(|_| format!("Invalid block id {}", self.id))?;
+        Uuid::parse_str(&self.parent_id).map_err(|_| format!("Invalid parent id for block {}", self.id))?;
+        DateTime::parse_from_rfc3339(&self.created_at_utc).map_err(|_| format!("Invalid creation date for block {}", self.id))?;
+        DateTime::parse_from_rfc3339(&self.updated_at_utc).map_err(|_| format!("Invalid update date for block {}", self.id))?;
+        BlockContent::from_json_string(&self.data).map_err(|_| format!("Invalid data for block {}", self.id))?;
+
+        Ok(())
+    }
+
+    fn insert(&self, connection: &Connection) -> rusqlite::Result<usize> {
+        connection.execute(
+            "INSERT INTO blocks (id, parent_id, block_type, data, block_order, favorite, created_at_utc, updated_at_utc) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                &self.id,
+                &self.parent_id,
+                &self.block_type,
+                &self.data,
+                &self.block_order,
+                &self.favorite,
+                &self.created_at_utc,
+                &self.updated_at_utc
+            ],
+        )
+    }
+}
+
+// Upgrades rows written by older format versions, the step at index `n` upgrades version `n + 1`.
+const ROW_UPGRADES: &[fn(BlockRow) -> BlockRow] = &[];
+
+fn migrate_row(row: BlockRow, format_version: u32) -> BlockRow {
+    ROW_UPGRADES
+        .iter()
+        .skip(format_version as usize - 1)
+        .fold(row, |row, upgrade| upgrade(row))
+}
+
+fn write_workspace(connection: &Connection, header: &WorkspaceHeader, writer: &mut impl Write) -> Result<usize, String> {
+    let header_fields = match serde_json::to_value(header) {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        _ => return Err(String::from("Could not serialize export header")),
+    };
+
+    let mut statement = connection
+        .prepare(&format!("{} SELECT * FROM blocks WHERE id IN tree ORDER BY parent_id, block_order ASC", WORKSPACE_TREE))
+        .map_err(|_| String::from("Could not read blocks"))?;
+    let rows = statement
+        .query_map([header.workspace_id.to_string()], BlockRow::from_row)
+        .map_err(|_| String::from("Could not read blocks"))?;
+
+    let io_error = |e: std::io::Error| {
+        log::error!("Could not write workspace export: {:?}", e);
+        String::from("Could not write workspace export")
+    };
+
+    writer.write_all(b"{").map_err(io_error)?;
+    for (key, value) in header_fields {
+        write!(writer, "{}:{},", serde_json::Value::String(key), value).map_err(io_error)?;
+    }
+    writer.write_all(b"\"blocks\":[").map_err(io_error)?;
+
+    let mut count = 0;
+    for row in rows {
+        let row = row.map_err(|_| String::from("Could not read blocks"))?;
+        if count > 0 {
+            writer.write_all(b",").map_err(io_error)?;
+        }
+        serde_json::to_writer(&mut *writer, &row).map_err(|_| String::from("Could not serialize block"))?;
+        count += 1;
+    }
+
+    writer.write_all(b"]}").map_err(io_error)?;
+    writer.flush().map_err(io_error)?;
+
+    Ok(count)
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceImportSummary {
+    pub workspace_id: Uuid,
+    pub configuration_version: String,
+    pub blocks: usize,
+}
+
+#[derive(Default)]
+struct PartialHeader {
+    format: Option<String>,
+    format_version: Option<u32>,
+    schema_version: Option<u32>,
+    configuration_version: Option<String>,
+    workspace_id: Option<Uuid>,
+}
+
+impl PartialHeader {
+    fn check_compatibility(&self) -> Result<u32, String> {
+        if self.format.as_deref() != Some(FORMAT_NAME) {
+            return Err(String::from("Not a my-blocks workspace export"));
+        }
+
+        let format_version = self.format_version.ok_or("Export has no format version")?;
+        if format_version == 0 || format_version > FORMAT_VERSION {
+            return Err(format!("Unsupported export format version {}", format_version));
+        }
+
+        let schema_version = self.schema_version.ok_or("Export has no schema version")?;
+        if schema_version > SCHEMA_VERSION {
+            return Err(format!(
+                "Export was made with a newer database schema ({} > {})",
+                schema_version, SCHEMA_VERSION
+            ));
+        }
+
+        Ok(format_version)
+    }
+}
+
+struct WorkspaceVisitor<'a> {
+    connection: &'a Connection,
+}
+
+impl<'de, 'a> Visitor<'de> for WorkspaceVisitor<'a> {
+    type Value = WorkspaceImportSummary;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a my-blocks workspace export")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut header = PartialHeader::default();
+        let mut imported_blocks = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "format" => header.format = Some(map.next_value()?),
+                "format_version" => header.format_version = Some(map.next_value()?),
+                "schema_version" => header.schema_version = Some(map.next_value()?),
+                "configuration_version" => header.configuration_version = Some(map.next_value()?),
+                "workspace_id" => header.workspace_id = Some(map.next_value()?),
+                "blocks" => {
+                    let format_version = header.check_compatibility().map_err(A::Error::custom)?;
+                    let workspace_id = header.workspace_id.ok_or_else(|| A::Error::missing_field("workspace_id"))?;
+
+                    // Blocks deleted since the export must not survive the restore.
+                    self.connection
+                        .execute(&format!("{} DELETE FROM blocks WHERE id IN tree", WORKSPACE_TREE), [workspace_id.to_string()])
+                        .map_err(|e| A::Error::custom(format!("Could not replace workspace {}: {}", workspace_id, e)))?;
+
+                    imported_blocks = Some(map.next_value_seed(BlockRowsSeed {
+                        connection: self.connection,
+                        format_version,
+                    })?);
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(WorkspaceImportSummary {
+            workspace_id: header.workspace_id.ok_or_else(|| A::Error::missing_field("workspace_id"))?,
+            configuration_version: header.configuration_version.unwrap_or_default(),
+            blocks: imported_blocks.ok_or_else(|| A::Error::missing_field("blocks"))?,
+        })
+    }
+}
+
+// Inserts the rows one by one while they are read, so the export never has to fit in memory.
+struct BlockRowsSeed<'a> {
+    connection: &'a Connection,
+    format_version: u32,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for BlockRowsSeed<'a> {
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for BlockRowsSeed<'a> {
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of blocks")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut count = 0;
+
+        while let Some(row) = seq.next_element::<BlockRow>()? {
+            let row = migrate_row(row, self.format_version);
+            row.validate().map_err(A::Error::custom)?;
+            row.insert(self.connection).map_err(|e| A::Error::custom(format!("Could not restore block {}: {}", row.id, e)))?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+#[tauri::command]
+pub fn export_workspace_json_command(
+    path: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+) -> Result<String, String> {
+    log::debug!("Running export_workspace_json_command to {}", path);
+    let connection = db.get()?;
+
+    let configuration = configuration.read().unwrap();
+    let header = WorkspaceHeader {
+        format: String::from(FORMAT_NAME),
+        format_version: FORMAT_VERSION,
+        schema_version: SCHEMA_VERSION,
+        configuration_version: configuration.version.clone(),
+        workspace_id: configuration.workspace_id,
+        exported_at_utc: Utc::now(),
+    };
+
+    // Written next to the destination first, so a failed export never leaves a truncated file behind.
+    let path = PathBuf::from(path);
+    let partial_path = path.with_extension("json.partial");
+
+    let file = File::create(&partial_path).map_err(|e| {
+        log::error!("Could not create {:?}: {:?}", &partial_path, e);
+        String::from("Could not create export file")
+    })?;
+
+    let count = match write_workspace(&connection, &header, &mut BufWriter::new(file)) {
+        Ok(count) => count,
+        Err(e) => {
+            remove_partial_export(&partial_path);
+            return Err(e);
+        }
+    };
+
+    if let Err(e) = std::fs::rename(&partial_path, &path) {
+        log::error!("Could not move export to {:?}: {:?}", &path, e);
+        remove_partial_export(&partial_path);
+        return Err(String::from("Could not write export file"));
+    }
+
+    log::debug!("Exported {} blocks", count);
+
+    Ok(serde_json::to_string(&header).unwrap())
+}
+
+fn remove_partial_export(partial_path: &Path) {
+    if let Err(e) = std::fs::remove_file(partial_path) {
+        log::error!("Could not remove {:?}: {:?}", partial_path, e);
+    }
+}
+
+// Restores a workspace export, replacing everything stored in that workspace.
+pub fn import_workspace_file(path: &Path, connection: &mut Connection) -> Result<WorkspaceImportSummary, String> {
+    let file = File::open(path).map_err(|e| {
+        log::error!("Could not open {:?}: {:?}", path, e);
+        String::from("Could not open export file")
+    })?;
+
+    let transaction = connection.transaction().map_err(|_| String::from("Could not start transaction"))?;
+
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
+    let summary = deserializer
+        .deserialize_map(WorkspaceVisitor {
+            connection: &transaction,
+        })
+        .and_then(|summary| deserializer.end().map(|_| summary))
+        .map_err(|e| {
+            log::error!("Could not import workspace export: {:?}", e);
+            format!("Could not import workspace export: {}", e)
+        })?;
+
+    if let Err(e) = transaction.commit() {
+        log::error!("Could not commit workspace import: {:?}", e);
+        return Err(String::from("Could not import workspace export"));
+    }
+
+    log::debug!("Restored {} blocks of workspace {}", summary.blocks, summary.workspace_id);
+
+    Ok(summary)
+}
+
+// Restores the workspace and switches to it.
+#[tauri::command]
+pub fn import_workspace_json_command(
+    path: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running import_workspace_json_command from {}", path);
+
+    let mut connection = db.get()?;
+    let summary = import_workspace_file(Path::new(&path), &mut connection)?;
+
+    emit_block_event(&app, BlockEvent::Updated, vec![summary.workspace_id], Vec::new());
+
+    let workspace = activate_workspace(summary.workspace_id, &mut configuration.write().unwrap(), &connection)?;
+    emit_app_event(&app, WORKSPACE_SWITCHED_EVENT, workspace);
+
+    Ok(serde_json::to_string(&summary).unwrap())
+}
@@ -1,14 +1,89 @@
-// Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
-#[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
-}
+pub mod attachments;
+pub mod backups;
+pub mod blocks;
+pub mod configuration;
+pub mod events;
+pub mod interchange;
+pub mod storage;
+pub mod windows;
+pub mod workspaces;
+
+use std::sync::RwLock;
+
+use clap::Parser;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let arguments = configuration::Arguments::parse();
+    let configuration = configuration::Configuration::init(arguments).unwrap();
+    log::info!("Starting My Blocks!");
+
+    // An encrypted database opened without a passphrase, or with a wrong one, waits for the app to unlock it.
+    let database = match storage::setup_database(&configuration) {
+        Ok(db_pool) => storage::Database::new(db_pool),
+        Err(e) if storage::encryption::is_encrypted(&configuration.db_path) => {
+            log::warn!("Database is locked: {}", e);
+            storage::Database::locked()
+        }
+        Err(e) => panic!("Could not set up database. {}", e),
+    };
+
+    let window_states = windows::WindowStateStore::load(&configuration);
+
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
-        .invoke_handler(tauri::generate_handler![greet])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .setup(|app| {
+            // Hand edits to the config file are picked up while running, a failure only loses that.
+            if let Err(e) = configuration::watch_configuration(app.handle().clone()) {
+                log::error!("Configuration changes will not be reloaded: {}", e);
+            }
+            backups::start_scheduler(app.handle().clone());
+            Ok(())
+        })
+        .register_uri_scheme_protocol(attachments::URI_SCHEME, |context, request| {
+            attachments::serve(context.app_handle(), request)
+        })
+        .manage(database)
+        .manage(RwLock::new(configuration))
+        .manage(window_states)
+        .invoke_handler(tauri::generate_handler![
+            configuration::load_configuration_command,
+            configuration::update_configuration_command,
+            blocks::create_block_command,
+            blocks::get_block_command,
+            blocks::load_blocks_for_page_command,
+            blocks::change_block_order_command,
+            blocks::load_home_page_command,
+            windows::open_page_window_command,
+            interchange::markdown_import::import_markdown_command,
+            interchange::markdown_export::export_page_markdown_command,
+            interchange::workspace_json::export_workspace_json_command,
+            interchange::workspace_json::import_workspace_json_command,
+            interchange::obsidian::import_obsidian_vault_command,
+            interchange::notion::import_notion_export_command,
+            interchange::opml::import_opml_command,
+            interchange::opml::export_opml_command,
+            interchange::site::publish_site_command,
+            workspaces::list_workspaces_command,
+            workspaces::create_workspace_command,
+            workspaces::rename_workspace_command,
+            workspaces::switch_workspace_command,
+            storage::open_database_command,
+            storage::encryption::database_status_command,
+            storage::encryption::unlock_database_command,
+            storage::encryption::change_passphrase_command,
+            storage::encryption::encrypt_database_command,
+            storage::integrity::check_integrity_command,
+            storage::maintenance::database_stats_command,
+            storage::maintenance::compact_database_command,
+            backups::list_backups_command,
+            backups::restore_backup_command,
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                backups::backup_on_exit(app);
+            }
+        });
 }
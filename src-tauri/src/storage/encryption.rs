@@ -0,0 +1,287 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use rusqlite::{Connection, DatabaseName, ErrorCode};
+use serde::Serialize;
+use tauri::{AppHandle, State};
+
+use super::{migrations, open_connection, setup_database, Database};
+use crate::backups::BackupStore;
+use crate::configuration::Configuration;
+use crate::events::{emit_app_event, DATABASE_OPENED_EVENT};
+
+// SQLCipher encrypts the whole file, only a plaintext database starts with the SQLite header.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+const ENCRYPTED_SCHEMA: &str = "encrypted";
+
+// Kept in memory only, and never printed in logs.
+#[derive(Clone, PartialEq)]
+pub struct Passphrase(String);
+
+impl Passphrase {
+    pub fn new(passphrase: String) -> Self {
+        Passphrase(passphrase)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Passphrase(..)")
+    }
+}
+
+impl FromStr for Passphrase {
+    type Err = std::convert::Infallible;
+
+    fn from_str(passphrase: &str) -> Result<Self, Self::Err> {
+        Ok(Passphrase(passphrase.to_string()))
+    }
+}
+
+// Missing and empty files become plaintext databases.
+pub fn is_encrypted(db_path: &Path) -> bool {
+    let mut header = [0u8; 16];
+
+    match File::open(db_path).and_then(|mut file| file.read_exact(&mut header)) {
+        Ok(_) => header != *SQLITE_HEADER,
+        Err(_) => false,
+    }
+}
+
+// Has to be the first statement on a connection.
+pub fn apply_key(connection: &Connection, passphrase: &Passphrase) -> rusqlite::Result<()> {
+    connection.pragma_update(None, "key", passphrase.as_str())
+}
+
+fn key_error(e: rusqlite::Error) -> String {
+    match e.sqlite_error_code() {
+        Some(ErrorCode::NotADatabase) => String::from("Wrong passphrase, or the file is not a database"),
+        _ => {
+            log::error!("Could not open database: {:?}", e);
+            String::from("Could not open database")
+        }
+    }
+}
+
+// SQLCipher accepts any key and only fails once the database is read, so this reads the schema on a
+// single connection before a pool opens several.
+pub fn check_key(db_path: &Path, passphrase: Option<&Passphrase>) -> Result<(), String> {
+    if passphrase.is_none() && is_encrypted(db_path) {
+        return Err(String::from("Database is encrypted, a passphrase is needed"));
+    }
+
+    let connection = Connection::open(db_path).map_err(key_error)?;
+    if let Some(passphrase) = passphrase {
+        apply_key(&connection, passphrase).map_err(key_error)?;
+    }
+
+    connection
+        .query_row("SELECT COUNT(*) FROM sqlite_master", [], |_| Ok(()))
+        .map_err(key_error)
+}
+
+fn sqlcipher_error(e: rusqlite::Error) -> String {
+    log::error!("SQLCipher error: {:?}", e);
+    String::from("Could not encrypt database")
+}
+
+// Writes an encrypted copy of the database behind `connection` to `destination`.
+fn export_encrypted(connection: &Connection, destination: &Path, passphrase: &Passphrase) -> Result<(), String> {
+    let version = migrations::current_version(connection).map_err(sqlcipher_error)?;
+
+    connection
+        .execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            rusqlite::params![destination.to_string_lossy(), passphrase.as_str()],
+        )
+        .map_err(sqlcipher_error)?;
+
+    let exported = connection
+        .query_row("SELECT sqlcipher_export(?1)", [ENCRYPTED_SCHEMA], |_| Ok(()))
+        // The export copies tables and indexes but not the schema version.
+        .and_then(|_| connection.pragma_update(Some(DatabaseName::Attached(ENCRYPTED_SCHEMA)), "user_version", version));
+
+    connection.execute("DETACH DATABASE encrypted", []).map_err(sqlcipher_error)?;
+    exported.map_err(sqlcipher_error)
+}
+
+pub(super) fn sidecar_path(db_path: &Path, suffix: &str) -> PathBuf {
+    let mut path = db_path.as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseStatus {
+    pub locked: bool,
+    pub encrypted: bool,
+}
+
+#[tauri::command]
+pub fn database_status_command(
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+) -> Result<String, String> {
+    log::debug!("Running database_status_command");
+    let configuration = configuration.read().unwrap();
+
+    Ok(serde_json::to_string(&DatabaseStatus {
+        locked: db.is_locked(),
+        encrypted: configuration.passphrase.is_some() || is_encrypted(&configuration.db_path),
+    })
+    .unwrap())
+}
+
+#[tauri::command]
+pub fn unlock_database_command(
+    passphrase: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running unlock_database_command");
+    let mut configuration = configuration.write().unwrap();
+
+    let mut unlocked = configuration.clone();
+    unlocked.passphrase = Some(Passphrase::new(passphrase));
+    let pool = setup_database(&unlocked)?;
+
+    db.replace(pool);
+    *configuration = unlocked;
+    log::info!("Unlocked database {:?}", &configuration.db_path);
+
+    emit_app_event(&app, DATABASE_OPENED_EVENT, configuration.db_path.clone());
+
+    Ok(serde_json::to_string(&*configuration).unwrap())
+}
+
+#[tauri::command]
+pub fn change_passphrase_command(
+    current_passphrase: String,
+    new_passphrase: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running change_passphrase_command");
+    let mut configuration = configuration.write().unwrap();
+
+    if configuration.passphrase.as_ref().map(Passphrase::as_str) != Some(current_passphrase.as_str()) {
+        return Err(String::from("Current passphrase is wrong"));
+    }
+    if new_passphrase.is_empty() {
+        return Err(String::from("New passphrase can not be empty"));
+    }
+
+    // Snapshots keep the passphrase they were taken with.
+    let previous = BackupStore::for_configuration(&configuration).create(&*db.get()?)?;
+
+    let mut changed = configuration.clone();
+    changed.passphrase = Some(Passphrase::new(new_passphrase));
+
+    // Connections of the pool hold the previous key, every one is returned and closed before the database is rekeyed.
+    let _closed = db.close(Duration::from_millis(configuration.database.connection_timeout_ms))?;
+    let rekeyed = open_connection(&configuration).and_then(|connection| {
+        connection
+            .pragma_update(None, "rekey", changed.passphrase.as_ref().unwrap().as_str())
+            .map_err(sqlcipher_error)
+    });
+    if let Err(e) = rekeyed {
+        db.replace(setup_database(&configuration)?);
+        return Err(e);
+    }
+
+    db.replace(setup_database(&changed)?);
+    *configuration = changed;
+    log::info!("Changed passphrase of {:?}, previous snapshot {}", &configuration.db_path, &previous.file_name);
+
+    emit_app_event(&app, DATABASE_OPENED_EVENT, configuration.db_path.clone());
+
+    Ok(serde_json::to_string(&previous).unwrap())
+}
+
+#[derive(Debug, Serialize)]
+pub struct EncryptionSummary {
+    pub db_path: PathBuf,
+    // Snapshots taken before encrypting stay readable without the passphrase until they are deleted.
+    pub unencrypted_backups: usize,
+}
+
+#[tauri::command]
+pub fn encrypt_database_command(
+    passphrase: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running encrypt_database_command");
+    let mut configuration = configuration.write().unwrap();
+
+    if configuration.passphrase.is_some() || is_encrypted(&configuration.db_path) {
+        return Err(String::from("Database is already encrypted"));
+    }
+    if passphrase.is_empty() {
+        return Err(String::from("Passphrase can not be empty"));
+    }
+
+    let mut encrypted = configuration.clone();
+    encrypted.passphrase = Some(Passphrase::new(passphrase));
+    let db_path = configuration.db_path.clone();
+    let encrypted_path = sidecar_path(&db_path, ".encrypted");
+
+    // Every connection of the pool is returned and closed first, none may write to the plaintext
+    // file or its write-ahead log while they are copied and replaced.
+    let _closed = db.close(Duration::from_millis(configuration.database.connection_timeout_ms))?;
+    let exported = open_connection(&configuration)
+        .and_then(|connection| {
+            // Everything in the write-ahead log goes into the database file before it is copied.
+            connection
+                .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+                .map_err(sqlcipher_error)?;
+
+            let _ = std::fs::remove_file(&encrypted_path);
+            export_encrypted(&connection, &encrypted_path, encrypted.passphrase.as_ref().unwrap())
+        })
+        .and_then(|_| check_key(&encrypted_path, encrypted.passphrase.as_ref()))
+        .and_then(|_| {
+            std::fs::rename(&encrypted_path, &db_path).map_err(|e| {
+                log::error!("Could not replace {:?} with its encrypted copy: {:?}", &db_path, e);
+                String::from("Could not replace the database with its encrypted copy")
+            })
+        });
+    if let Err(e) = exported {
+        let _ = std::fs::remove_file(&encrypted_path);
+        db.replace(setup_database(&configuration)?);
+        return Err(e);
+    }
+    for suffix in ["-wal", "-shm"] {
+        let _ = std::fs::remove_file(sidecar_path(&db_path, suffix));
+    }
+
+    let pool = setup_database(&encrypted)?;
+    db.replace(pool);
+    *configuration = encrypted;
+    log::info!("Encrypted database {:?}", &db_path);
+
+    emit_app_event(&app, DATABASE_OPENED_EVENT, db_path.clone());
+
+    let unencrypted_backups = BackupStore::for_configuration(&configuration).list()?.len();
+    if unencrypted_backups > 0 {
+        log::warn!("{} backups taken before encrypting are not encrypted", unencrypted_backups);
+    }
+
+    Ok(serde_json::to_string(&EncryptionSummary {
+        db_path,
+        unencrypted_backups,
+    })
+    .unwrap())
+}
@@ -0,0 +1,418 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::RwLock;
+
+use chrono::DateTime;
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+use super::Database;
+use crate::blocks::{Block, BlockContent};
+use crate::configuration::Configuration;
+use crate::events::{emit_block_event, BlockEvent};
+use crate::workspaces::load_workspaces;
+
+const RECOVERED_PAGE_TITLE: &str = "Recovered";
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Serialize)]
+pub struct OrderProblem {
+    pub parent_id: String,
+    // Orders shared by more than one sibling.
+    pub duplicates: Vec<i64>,
+    // Orders missing between the first and the last sibling.
+    pub gaps: Vec<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MalformedBlock {
+    pub id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceProblem {
+    pub workspace_id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepairSummary {
+    pub recovered_page_id: Option<Uuid>,
+    pub orphans_moved: usize,
+    pub parents_renumbered: usize,
+}
+
+// Checks are read with plain queries, loading the rows as blocks would fail on the rows being checked.
+#[derive(Debug, Default, Serialize)]
+pub struct IntegrityReport {
+    pub ok: bool,
+    // What `PRAGMA integrity_check` found wrong with the file itself.
+    pub sqlite: Vec<String>,
+    // Blocks whose parent does not exist, or the first created block of a loop of parents, so they
+    // and the blocks below them are not reachable from any workspace.
+    pub orphans: Vec<String>,
+    pub orders: Vec<OrderProblem>,
+    // Rows that can not be loaded as blocks.
+    pub malformed: Vec<MalformedBlock>,
+    // Several workspaces are fine, a workspace nested in a block or without a home page is not.
+    pub workspaces: Vec<WorkspaceProblem>,
+    pub repair: Option<RepairSummary>,
+}
+
+impl IntegrityReport {
+    // Orphans and orders can be repaired, the other problems need a backup.
+    fn is_repairable(&self) -> bool {
+        !self.orphans.is_empty() || !self.orders.is_empty()
+    }
+}
+
+pub fn check(connection: &Connection) -> rusqlite::Result<IntegrityReport> {
+    let mut report = IntegrityReport {
+        sqlite: check_sqlite(connection)?,
+        orphans: find_orphans(connection)?,
+        orders: check_orders(connection)?,
+        malformed: find_malformed(connection)?,
+        workspaces: check_workspaces(connection)?,
+        ..Default::default()
+    };
+    report.ok = report.sqlite.is_empty()
+        && report.orphans.is_empty()
+        && report.orders.is_empty()
+        && report.malformed.is_empty()
+        && report.workspaces.is_empty();
+
+    Ok(report)
+}
+
+fn check_sqlite(connection: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut statement = connection.prepare("PRAGMA integrity_check")?;
+    let messages = statement
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+
+    // A sound file reports a single "ok".
+    Ok(messages.into_iter().filter(|message| message != "ok").collect())
+}
+
+// Workspaces have no parent, their `parent_id` is a random id.
+fn find_orphans(connection: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut statement = connection.prepare("SELECT id, parent_id, block_type FROM blocks ORDER BY created_at_utc ASC")?;
+    let blocks = statement
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?
+        .collect::<rusqlite::Result<Vec<(String, String, String)>>>()?;
+
+    let parents: HashMap<&str, &str> = blocks.iter().map(|(id, parent_id, _)| (id.as_str(), parent_id.as_str())).collect();
+    let created_order: HashMap<&str, usize> = blocks.iter().enumerate().map(|(order, (id, _, _))| (id.as_str(), order)).collect();
+    let workspaces: HashSet<&str> = blocks
+        .iter()
+        .filter(|(_, _, block_type)| block_type == "workspace")
+        .map(|(id, _, _)| id.as_str())
+        .collect();
+
+    let orphans: HashSet<&str> = blocks
+        .iter()
+        .filter_map(|(id, _, _)| orphan_above(id, &parents, &workspaces, &created_order))
+        .collect();
+
+    Ok(blocks
+        .iter()
+        .filter(|(id, _, _)| orphans.contains(id.as_str()))
+        .map(|(id, _, _)| id.clone())
+        .collect())
+}
+
+// Follows the parents of a block up to its workspace. Returns the block at the top of a chain whose
+// parent is missing, or the first created block of a loop the chain runs into.
+fn orphan_above<'a>(
+    id: &'a str,
+    parents: &HashMap<&'a str, &'a str>,
+    workspaces: &HashSet<&str>,
+    created_order: &HashMap<&str, usize>,
+) -> Option<&'a str> {
+    let mut chain: Vec<&str> = Vec::new();
+    let mut current = id;
+
+    loop {
+        if workspaces.contains(current) {
+            return None;
+        }
+        if let Some(start) = chain.iter().position(|block_id| *block_id == current) {
+            return chain[start..].iter().min_by_key(|block_id| created_order[*block_id]).copied();
+        }
+        chain.push(current);
+
+        match parents.get(current) {
+            Some(parent_id) if parents.contains_key(parent_id) => current = parent_id,
+            _ => return Some(current),
+        }
+    }
+}
+
+// The siblings of a parent are ordered 0, 1, 2 and so on.
+fn check_orders(connection: &Connection) -> rusqlite::Result<Vec<OrderProblem>> {
+    let mut statement = connection
+        .prepare("SELECT parent_id, block_order FROM blocks ORDER BY parent_id, block_order")?;
+    let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?;
+
+    let mut siblings: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+    for row in rows {
+        let (parent_id, block_order) = row?;
+        siblings.entry(parent_id).or_default().push(block_order);
+    }
+
+    let mut problems = Vec::new();
+    for (parent_id, orders) in siblings {
+        let mut duplicates: Vec<i64> = orders.windows(2).filter(|pair| pair[0] == pair[1]).map(|pair| pair[0]).collect();
+        duplicates.dedup();
+        let last = orders.last().copied().unwrap_or_default();
+        let gaps: Vec<i64> = (0..last).filter(|order| orders.binary_search(order).is_err()).collect();
+
+        if !duplicates.is_empty() || !gaps.is_empty() || orders.first().is_some_and(|first| *first < 0) {
+            problems.push(OrderProblem {
+                parent_id,
+                duplicates,
+                gaps,
+            });
+        }
+    }
+
+    Ok(problems)
+}
+
+fn find_malformed(connection: &Connection) -> rusqlite::Result<Vec<MalformedBlock>> {
+    // Cast, so a value stored with the wrong type is reported instead of failing the query.
+    let mut statement = connection.prepare(
+        "SELECT CAST(id AS TEXT), CAST(parent_id AS TEXT), CAST(data AS TEXT), CAST(created_at_utc AS TEXT), CAST(updated_at_utc AS TEXT)
+        FROM blocks ORDER BY created_at_utc ASC",
+    )?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+            row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+            row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+        ))
+    })?;
+
+    let mut malformed = Vec::new();
+    for row in rows {
+        let (id, parent_id, data, created_at_utc, updated_at_utc) = row?;
+
+        let reason = if Uuid::parse_str(&id).is_err() {
+            Some(String::from("Invalid id"))
+        } else if Uuid::parse_str(&parent_id).is_err() {
+            Some(String::from("Invalid parent id"))
+        } else if let Err(e) = BlockContent::from_json_string(&data) {
+            Some(format!("Invalid data: {}", e))
+        } else if DateTime::parse_from_rfc3339(&created_at_utc).is_err() {
+            Some(String::from("Invalid creation date"))
+        } else if DateTime::parse_from_rfc3339(&updated_at_utc).is_err() {
+            Some(String::from("Invalid update date"))
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            malformed.push(MalformedBlock { id, reason });
+        }
+    }
+
+    Ok(malformed)
+}
+
+fn check_workspaces(connection: &Connection) -> rusqlite::Result<Vec<WorkspaceProblem>> {
+    let mut statement = connection.prepare(
+        "SELECT id, data, EXISTS (SELECT 1 FROM blocks AS parent WHERE parent.id = workspace.parent_id)
+        FROM blocks AS workspace WHERE block_type = 'workspace' ORDER BY created_at_utc ASC",
+    )?;
+    let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, bool>(2)?)))?;
+
+    let mut problems = Vec::new();
+    for row in rows {
+        let (workspace_id, data, nested) = row?;
+
+        if nested {
+            problems.push(WorkspaceProblem {
+                workspace_id: workspace_id.clone(),
+                reason: String::from("Workspace is nested in another block"),
+            });
+        }
+
+        // Malformed data is already reported with the malformed blocks.
+        let Ok(content) = BlockContent::from_json_string(&data) else {
+            continue;
+        };
+        let home_page_id = content.contents.unwrap_or_default();
+        let home_page_type: Option<String> = if Uuid::parse_str(&home_page_id).is_ok() {
+            connection
+                .query_row("SELECT block_type FROM blocks WHERE id = ?1", [&home_page_id], |row| row.get(0))
+                .map(Some)
+                .or_else(|e| match e {
+                    rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                    e => Err(e),
+                })?
+        } else {
+            None
+        };
+
+        if home_page_type.as_deref() != Some("page") {
+            problems.push(WorkspaceProblem {
+                workspace_id,
+                reason: String::from("Workspace has no home page"),
+            });
+        }
+    }
+
+    Ok(problems)
+}
+
+struct Repair {
+    summary: RepairSummary,
+    moved_ids: Vec<Uuid>,
+    renumbered_parent_ids: Vec<Uuid>,
+}
+
+// Moves orphans into a "Recovered" page below the home page of the active workspace, and renumbers the
+// siblings of every parent with broken orders. Either everything is repaired or nothing is.
+fn repair(connection: &mut Connection, report: &IntegrityReport, workspace_id: Uuid) -> Result<Repair, String> {
+    let transaction = connection.transaction().map_err(integrity_error)?;
+
+    let mut parent_ids: Vec<String> = report.orders.iter().map(|problem| problem.parent_id.clone()).collect();
+    let mut recovered_page_id = None;
+    let mut moved_ids = Vec::new();
+
+    if !report.orphans.is_empty() {
+        let home_page_id = load_workspaces(workspace_id, &transaction)?
+            .into_iter()
+            .find(|workspace| workspace.active)
+            .and_then(|workspace| workspace.home_page_id)
+            .ok_or_else(|| String::from("The active workspace has no home page to recover blocks into"))?;
+        let page_id = find_or_create_recovered_page(home_page_id, &transaction)?;
+
+        let first_order: i64 = transaction
+            .query_row(
+                "SELECT COALESCE(MAX(block_order) + 1, 0) FROM blocks WHERE parent_id = ?1",
+                [page_id.to_string()],
+                |row| row.get(0),
+            )
+            .map_err(integrity_error)?;
+        for (offset, orphan_id) in report.orphans.iter().enumerate() {
+            // A block taken out of a loop leaves a gap among the siblings it had.
+            let previous_parent_id: String = transaction
+                .query_row("SELECT parent_id FROM blocks WHERE id = ?1", [orphan_id], |row| row.get(0))
+                .map_err(integrity_error)?;
+            parent_ids.push(previous_parent_id);
+
+            transaction
+                .execute(
+                    "UPDATE blocks SET parent_id = ?1, block_order = ?2 WHERE id = ?3",
+                    rusqlite::params![page_id.to_string(), first_order + offset as i64, orphan_id],
+                )
+                .map_err(integrity_error)?;
+            moved_ids.extend(Uuid::parse_str(orphan_id).ok());
+        }
+
+        log::info!("Moved {} orphaned blocks to page {}", moved_ids.len(), page_id);
+        parent_ids.push(page_id.to_string());
+        recovered_page_id = Some(page_id);
+    }
+
+    let mut renumbered_parent_ids = Vec::new();
+    parent_ids.sort();
+    parent_ids.dedup();
+    for parent_id in &parent_ids {
+        if renumber_children(parent_id, &transaction).map_err(integrity_error)? {
+            renumbered_parent_ids.extend(Uuid::parse_str(parent_id).ok());
+        }
+    }
+
+    transaction.commit().map_err(integrity_error)?;
+
+    Ok(Repair {
+        summary: RepairSummary {
+            recovered_page_id,
+            orphans_moved: report.orphans.len(),
+            parents_renumbered: renumbered_parent_ids.len(),
+        },
+        moved_ids,
+        renumbered_parent_ids,
+    })
+}
+
+// Reuses the page of an earlier repair.
+fn find_or_create_recovered_page(home_page_id: Uuid, connection: &Connection) -> Result<Uuid, String> {
+    let existing = connection.query_row(
+        "SELECT id FROM blocks WHERE parent_id = ?1 AND block_type = 'page' AND json_extract(data, '$.contents') = ?2 LIMIT 1",
+        rusqlite::params![home_page_id.to_string(), RECOVERED_PAGE_TITLE],
+        |row| row.get::<_, String>(0),
+    );
+
+    match existing {
+        Ok(id) => Uuid::parse_str(&id).map_err(|_| String::from("Recovered page has an invalid id")),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            let page = Block::new(home_page_id, String::from("page"), String::from(RECOVERED_PAGE_TITLE));
+            page.save(connection).map_err(|_| String::from("Could not create the recovered page"))?;
+
+            Ok(page.id)
+        }
+        Err(e) => Err(integrity_error(e)),
+    }
+}
+
+// Numbers the children 0, 1, 2 in their current order, returning whether any of them changed.
+fn renumber_children(parent_id: &str, connection: &Connection) -> rusqlite::Result<bool> {
+    let mut statement =
+        connection.prepare("SELECT id, block_order FROM blocks WHERE parent_id = ?1 ORDER BY block_order, created_at_utc, id")?;
+    let children = statement
+        .query_map([parent_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?
+        .collect::<rusqlite::Result<Vec<(String, i64)>>>()?;
+
+    let mut changed = false;
+    for (order, (id, block_order)) in children.iter().enumerate() {
+        if *block_order != order as i64 {
+            connection.execute("UPDATE blocks SET block_order = ?1 WHERE id = ?2", rusqlite::params![order as i64, id])?;
+            changed = true;
+        }
+    }
+
+    Ok(changed)
+}
+
+fn integrity_error(e: rusqlite::Error) -> String {
+    log::error!("Integrity check failed: {:?}", e);
+    String::from("Could not check the database")
+}
+
+#[tauri::command]
+pub fn check_integrity_command(
+    repair: bool,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running check_integrity_command, repair: {}", repair);
+    let mut connection = db.get()?;
+
+    let mut report = check(&connection).map_err(integrity_error)?;
+    if !report.ok {
+        log::warn!("Integrity check found problems: {:?}", &report);
+    }
+
+    if repair && report.is_repairable() {
+        let workspace_id = configuration.read().unwrap().workspace_id;
+        let repaired = self::repair(&mut connection, &report, workspace_id)?;
+
+        emit_block_event(&app, BlockEvent::Moved, repaired.moved_ids, repaired.renumbered_parent_ids);
+
+        // The report then describes the database as it is after the repair.
+        report = check(&connection).map_err(integrity_error)?;
+        report.repair = Some(repaired.summary);
+    }
+
+    Ok(serde_json::to_string(&report).unwrap())
+}
@@ -0,0 +1,126 @@
+use rusqlite::Connection;
+use uuid::Uuid;
+
+use super::{check, find_or_create_recovered_page, repair};
+use crate::blocks::repository::{self, BlockRepository};
+use crate::blocks::Block;
+use crate::storage::testing::in_memory_pool;
+
+// The home page of a new workspace with the blocks "0", "1" and so on.
+fn home_page_with_blocks(connection: &Connection, count: usize) -> (Uuid, Uuid, Vec<Uuid>) {
+    let repository = repository::for_connection(connection);
+    let workspace_id = Uuid::now_v7();
+    let (home_page, _) = Block::find_or_create_home_page(workspace_id, &repository).unwrap();
+
+    let ids = (0..count)
+        .map(|index| {
+            let block = Block::new(home_page.id, String::from("paragraph"), index.to_string());
+            repository.insert(&block).unwrap();
+            block.id
+        })
+        .collect();
+
+    (workspace_id, home_page.id, ids)
+}
+
+fn set_column(connection: &Connection, id: Uuid, column: &str, value: &str) {
+    connection
+        .execute(&format!("UPDATE blocks SET {} = ?1 WHERE id = ?2", column), [value, &id.to_string()])
+        .unwrap();
+}
+
+fn parent_and_order(connection: &Connection, id: Uuid) -> (String, i64) {
+    connection
+        .query_row("SELECT parent_id, block_order FROM blocks WHERE id = ?1", [id.to_string()], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap()
+}
+
+#[test]
+fn a_new_workspace_is_sound() {
+    let pool = in_memory_pool();
+    let connection = pool.get().unwrap();
+    home_page_with_blocks(&connection, 3);
+
+    let report = check(&connection).unwrap();
+
+    assert!(report.ok, "{:?}", report);
+}
+
+#[test]
+fn orphans_are_moved_into_a_recovered_page() {
+    let pool = in_memory_pool();
+    let mut connection = pool.get().unwrap();
+    let (workspace_id, home_page_id, ids) = home_page_with_blocks(&connection, 3);
+    set_column(&connection, ids[1], "parent_id", &Uuid::now_v7().to_string());
+
+    let report = check(&connection).unwrap();
+    assert_eq!(report.orphans, vec![ids[1].to_string()]);
+    // The block left a gap where it was.
+    assert!(report.orders.iter().any(|problem| problem.parent_id == home_page_id.to_string()));
+
+    let repaired = repair(&mut connection, &report, workspace_id).unwrap();
+    let recovered_page_id = repaired.summary.recovered_page_id.unwrap();
+
+    assert_eq!(parent_and_order(&connection, recovered_page_id).0, home_page_id.to_string());
+    assert_eq!(parent_and_order(&connection, ids[1]), (recovered_page_id.to_string(), 0));
+    assert_eq!(parent_and_order(&connection, ids[2]), (home_page_id.to_string(), 1));
+    assert!(check(&connection).unwrap().ok);
+}
+
+#[test]
+fn duplicate_orders_and_gaps_are_renumbered() {
+    let pool = in_memory_pool();
+    let mut connection = pool.get().unwrap();
+    let (workspace_id, home_page_id, ids) = home_page_with_blocks(&connection, 3);
+    set_column(&connection, ids[1], "block_order", "0");
+    set_column(&connection, ids[2], "block_order", "3");
+
+    let report = check(&connection).unwrap();
+    assert_eq!(report.orders.len(), 1);
+    assert_eq!(report.orders[0].parent_id, home_page_id.to_string());
+    assert_eq!(report.orders[0].duplicates, vec![0]);
+    assert_eq!(report.orders[0].gaps, vec![1, 2]);
+
+    let repaired = repair(&mut connection, &report, workspace_id).unwrap();
+
+    assert_eq!(repaired.summary.parents_renumbered, 1);
+    assert_eq!(repaired.summary.recovered_page_id, None);
+    let orders: Vec<i64> = ids.iter().map(|id| parent_and_order(&connection, *id).1).collect();
+    assert_eq!(orders, vec![0, 1, 2]);
+    assert!(check(&connection).unwrap().ok);
+}
+
+#[test]
+fn parent_loops_are_reported_as_orphans() {
+    let pool = in_memory_pool();
+    let mut connection = pool.get().unwrap();
+    let (workspace_id, _, ids) = home_page_with_blocks(&connection, 2);
+    let child = Block::new(ids[0], String::from("paragraph"), String::from("child"));
+    repository::for_connection(&connection).insert(&child).unwrap();
+    set_column(&connection, ids[0], "parent_id", &child.id.to_string());
+
+    let report = check(&connection).unwrap();
+    assert_eq!(report.orphans, vec![ids[0].to_string()]);
+
+    let repaired = repair(&mut connection, &report, workspace_id).unwrap();
+    let recovered_page_id = repaired.summary.recovered_page_id.unwrap();
+
+    // Taking the first block out of the loop keeps the one below it in place.
+    assert_eq!(parent_and_order(&connection, ids[0]).0, recovered_page_id.to_string());
+    assert_eq!(parent_and_order(&connection, child.id).0, ids[0].to_string());
+    assert!(check(&connection).unwrap().ok);
+}
+
+#[test]
+fn later_repairs_reuse_the_recovered_page() {
+    let pool = in_memory_pool();
+    let connection = pool.get().unwrap();
+    let (_, home_page_id, _) = home_page_with_blocks(&connection, 1);
+
+    let first = find_or_create_recovered_page(home_page_id, &connection).unwrap();
+    let second = find_or_create_recovered_page(home_page_id, &connection).unwrap();
+
+    assert_eq!(first, second);
+}
@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::{AppHandle, State};
+
+use super::encryption::sidecar_path;
+use super::Database;
+use crate::configuration::Configuration;
+use crate::events::{emit_app_event, DATABASE_COMPACTION_EVENT};
+
+// Blocks nested in a cycle would otherwise be followed forever.
+const MAX_NESTING: i64 = 1000;
+const LARGEST_PAGES: i64 = 10;
+const COMPACTION_STEPS: &[&str] = &["vacuum", "analyze", "checkpoint"];
+
+#[derive(Debug, Serialize)]
+pub struct PageSize {
+    pub id: String,
+    pub title: Option<String>,
+    // Blocks on the page, not counting the ones on pages nested in it.
+    pub blocks: i64,
+    pub bytes: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseStats {
+    pub db_path: PathBuf,
+    pub file_size: u64,
+    // Pages written since the last checkpoint, which are not in the database file yet.
+    pub wal_size: u64,
+    pub page_size: i64,
+    pub page_count: i64,
+    // Pages left empty by deleted blocks. The file only shrinks once they are vacuumed.
+    pub free_pages: i64,
+    pub block_counts: BTreeMap<String, i64>,
+    // Levels below a workspace, its home page is at level 1.
+    pub deepest_nesting: i64,
+    pub largest_pages: Vec<PageSize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompactionProgress {
+    pub step: String,
+    pub completed: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompactionSummary {
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|metadata| metadata.len()).unwrap_or_default()
+}
+
+// The database file together with its write-ahead log.
+fn total_size(db_path: &Path) -> u64 {
+    file_size(db_path) + file_size(&sidecar_path(db_path, "-wal"))
+}
+
+pub fn database_stats(connection: &Connection, db_path: &Path) -> rusqlite::Result<DatabaseStats> {
+    let mut statement = connection.prepare("SELECT block_type, COUNT(*) FROM blocks GROUP BY block_type ORDER BY block_type")?;
+    let block_counts = statement
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?
+        .collect::<rusqlite::Result<BTreeMap<String, i64>>>()?;
+
+    let deepest_nesting = connection.query_row(
+        "WITH RECURSIVE tree(id, depth) AS (
+            SELECT id, 0 FROM blocks WHERE block_type = 'workspace'
+            UNION
+            SELECT blocks.id, tree.depth + 1 FROM blocks JOIN tree ON blocks.parent_id = tree.id WHERE tree.depth < ?1
+        )
+        SELECT COALESCE(MAX(depth), 0) FROM tree",
+        [MAX_NESTING],
+        |row| row.get(0),
+    )?;
+
+    // Each page with the blocks below it, down to the next nested page.
+    let mut statement = connection.prepare(
+        "WITH RECURSIVE content(page_id, id, bytes) AS (
+            SELECT id, id, 0 FROM blocks WHERE block_type = 'page'
+            UNION
+            SELECT content.page_id, blocks.id, LENGTH(CAST(blocks.data AS BLOB))
+            FROM blocks JOIN content ON blocks.parent_id = content.id WHERE blocks.block_type NOT IN ('page', 'workspace')
+        )
+        SELECT page.id, json_extract(page.data, '$.contents'), COUNT(*) - 1, SUM(content.bytes)
+        FROM content JOIN blocks AS page ON page.id = content.page_id
+        GROUP BY page.id ORDER BY SUM(content.bytes) DESC LIMIT ?1",
+    )?;
+    let largest_pages = statement
+        .query_map([LARGEST_PAGES], |row| {
+            Ok(PageSize {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                blocks: row.get(2)?,
+                bytes: row.get(3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<PageSize>>>()?;
+
+    Ok(DatabaseStats {
+        db_path: db_path.to_path_buf(),
+        file_size: file_size(db_path),
+        wal_size: file_size(&sidecar_path(db_path, "-wal")),
+        page_size: connection.pragma_query_value(None, "page_size", |row| row.get(0))?,
+        page_count: connection.pragma_query_value(None, "page_count", |row| row.get(0))?,
+        free_pages: connection.pragma_query_value(None, "freelist_count", |row| row.get(0))?,
+        block_counts,
+        deepest_nesting,
+        largest_pages,
+    })
+}
+
+// Rewrites the database without its free pages, refreshes the statistics the query planner uses, and
+// moves the write-ahead log into the file. `progress` is called before each step and once at the end.
+pub fn compact(connection: &Connection, progress: impl Fn(CompactionProgress)) -> rusqlite::Result<()> {
+    let report = |completed: usize| {
+        progress(CompactionProgress {
+            step: String::from(COMPACTION_STEPS.get(completed).copied().unwrap_or("done")),
+            completed,
+            total: COMPACTION_STEPS.len(),
+        })
+    };
+
+    report(0);
+    connection.execute_batch("VACUUM")?;
+    report(1);
+    connection.execute_batch("ANALYZE")?;
+    report(2);
+    connection.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    report(3);
+
+    Ok(())
+}
+
+fn maintenance_error(e: rusqlite::Error) -> String {
+    log::error!("Database maintenance failed: {:?}", e);
+    String::from("Could not read database statistics")
+}
+
+#[tauri::command]
+pub fn database_stats_command(
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+) -> Result<String, String> {
+    log::debug!("Running database_stats_command");
+    let connection = db.get()?;
+    let db_path = configuration.read().unwrap().db_path.clone();
+
+    let stats = database_stats(&connection, &db_path).map_err(maintenance_error)?;
+
+    Ok(serde_json::to_string(&stats).unwrap())
+}
+
+// Compacting a large database takes a while, it runs on a blocking thread so other commands are answered meanwhile.
+#[tauri::command]
+pub async fn compact_database_command(
+    db: State<'_, Database>,
+    configuration: State<'_, RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running compact_database_command");
+    let connection = db.get()?;
+    let db_path = configuration.read().unwrap().db_path.clone();
+
+    let compaction = tauri::async_runtime::spawn_blocking(move || -> Result<CompactionSummary, String> {
+        let size_before = total_size(&db_path);
+        compact(&connection, |progress| emit_app_event(&app, DATABASE_COMPACTION_EVENT, progress)).map_err(|e| {
+            log::error!("Could not compact {:?}: {:?}", &db_path, e);
+            String::from("Could not compact database")
+        })?;
+        let size_after = total_size(&db_path);
+        log::info!("Compacted {:?} from {} to {} bytes", &db_path, size_before, size_after);
+
+        Ok(CompactionSummary {
+            size_before,
+            size_after,
+        })
+    });
+    let summary = compaction.await.map_err(|e| {
+        log::error!("Compaction did not finish: {:?}", e);
+        String::from("Could not compact database")
+    })??;
+
+    Ok(serde_json::to_string(&summary).unwrap())
+}
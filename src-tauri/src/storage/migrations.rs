@@ -0,0 +1,78 @@
+use rusqlite::Connection;
+
+// Applied in order, a database records how many it has in `PRAGMA user_version`.
+// Never edit or reorder a released migration, append a new one instead.
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "create blocks",
+        // Databases from before migrations were tracked already have the table.
+        "CREATE TABLE IF NOT EXISTS blocks (
+            id TEXT PRIMARY KEY,
+            parent_id TEXT NOT NULL,
+            block_type VARCHAR(255) NOT NULL,
+            data TEXT NOT NULL,
+            block_order INTEGER NOT NULL DEFAULT 0,
+            favorite BOOLEAN NOT NULL DEFAULT 0,
+            created_at_utc DATETIME NOT NULL,
+            updated_at_utc DATETIME NOT NULL
+        );",
+    ),
+    (
+        "index blocks parent_id",
+        "CREATE INDEX IF NOT EXISTS idx_parent_id ON blocks(parent_id);",
+    ),
+    (
+        "index blocks block_type",
+        "CREATE INDEX IF NOT EXISTS idx_block_type ON blocks(block_type);",
+    ),
+    (
+        "index blocks created_at_utc",
+        "CREATE INDEX IF NOT EXISTS idx_created_at_utc ON blocks(created_at_utc);",
+    ),
+    (
+        "index blocks updated_at_utc",
+        "CREATE INDEX IF NOT EXISTS idx_updated_at_utc ON blocks(updated_at_utc);",
+    ),
+];
+
+pub const LATEST_VERSION: u32 = MIGRATIONS.len() as u32;
+
+pub fn current_version(connection: &Connection) -> rusqlite::Result<u32> {
+    connection.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+// Fails without touching the database when it was migrated by a newer version of the app.
+pub fn check_version(connection: &Connection) -> Result<u32, String> {
+    let version = current_version(connection).map_err(|e| format!("Could not read database version: {}", e))?;
+
+    if version > LATEST_VERSION {
+        return Err(format!(
+            "Database has schema version {} but this version of My Blocks only supports up to {}",
+            version, LATEST_VERSION
+        ));
+    }
+
+    Ok(version)
+}
+
+// Runs every pending migration in its own transaction, so a failure leaves the database at the last one that worked.
+pub fn run_migrations(connection: &mut Connection) -> Result<(), String> {
+    let version = check_version(connection)?;
+
+    for (index, (name, sql)) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let target_version = index as u32 + 1;
+        log::info!("Running migration {} - {}", target_version, name);
+
+        let migration_error = |e: rusqlite::Error| {
+            log::error!("Migration {} - {} failed: {:?}", target_version, name, e);
+            format!("Could not run migration {} - {}", target_version, name)
+        };
+
+        let transaction = connection.transaction().map_err(migration_error)?;
+        transaction.execute_batch(sql).map_err(migration_error)?;
+        transaction.pragma_update(None, "user_version", target_version).map_err(migration_error)?;
+        transaction.commit().map_err(migration_error)?;
+    }
+
+    Ok(())
+}
@@ -1,17 +1,60 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
 use log;
-use r2d2::Pool;
+use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+use crate::blocks::repository;
+use crate::blocks::Block;
+use crate::configuration::{Configuration, DatabaseSettings};
+use crate::events::{emit_app_event, DATABASE_OPENED_EVENT};
+use crate::workspaces::load_workspaces;
+
+pub mod encryption;
+pub mod integrity;
+pub mod maintenance;
+#[cfg(test)]
+pub mod testing;
+mod migrations;
+#[cfg(test)]
+mod tests;
+
+pub use encryption::Passphrase;
+pub use migrations::{check_version, run_migrations};
+
+// Version of the database structure created by the migrations, recorded in workspace exports.
+pub const SCHEMA_VERSION: u32 = migrations::LATEST_VERSION;
 
 pub fn setup_database(
     configuration: &super::configuration::Configuration,
 ) -> Result<Pool<SqliteConnectionManager>, String> {
     log::debug!("Initializing db {:?}", &configuration.db_path);
-    let manager = SqliteConnectionManager::file(std::path::PathBuf::from(&configuration.db_path));
+    let settings = configuration.database.clone();
+    settings.validate()?;
+    encryption::check_key(&configuration.db_path, configuration.passphrase.as_ref())?;
+
+    let connection_settings = settings.clone();
+    let passphrase = configuration.passphrase.clone();
+    let manager = SqliteConnectionManager::file(std::path::PathBuf::from(&configuration.db_path))
+        .with_init(move |connection| configure_connection(connection, &connection_settings, passphrase.as_ref()));
     log::debug!("DB Was initialized");
 
-    match r2d2::Pool::new(manager) {
+    // Further connections open on demand, deriving the key of an encrypted database takes a moment each.
+    let pool = r2d2::Pool::builder()
+        .max_size(settings.pool_size)
+        .min_idle(Some(1))
+        .connection_timeout(Duration::from_millis(settings.connection_timeout_ms))
+        .build(manager);
+
+    match pool {
         Ok(pool) => {
-            setup_structure(&pool, configuration).unwrap();
+            setup_structure(&pool, configuration)?;
             log::debug!("Pool Was initialized");
             Ok(pool)
         }
@@ -22,42 +65,188 @@ pub fn setup_database(
     }
 }
 
-// TODO: Manage db versions
+// A connection outside of the pool, for work that needs the pool closed while it runs.
+pub fn open_connection(configuration: &super::configuration::Configuration) -> Result<Connection, String> {
+    let mut connection = Connection::open(&configuration.db_path).map_err(|e| {
+        log::error!("Could not open db {:?}: {:?}", &configuration.db_path, e);
+        String::from("Could not open database")
+    })?;
+    configure_connection(&mut connection, &configuration.database, configuration.passphrase.as_ref()).map_err(|e| {
+        log::error!("Could not configure db connection: {:?}", e);
+        String::from("Could not open database")
+    })?;
+
+    Ok(connection)
+}
+
+// Runs on every new connection of the pool, pragmas other than journal_mode only last as long as the connection.
+fn configure_connection(
+    connection: &mut Connection,
+    settings: &DatabaseSettings,
+    passphrase: Option<&Passphrase>,
+) -> rusqlite::Result<()> {
+    if let Some(passphrase) = passphrase {
+        encryption::apply_key(connection, passphrase)?;
+    }
+    connection.busy_timeout(Duration::from_millis(settings.busy_timeout_ms))?;
+
+    // In-memory and read-only databases keep their own journal mode.
+    let journal_mode: String = connection.pragma_update_and_check(None, "journal_mode", settings.journal_mode.as_pragma(), |row| row.get(0))?;
+    if !journal_mode.eq_ignore_ascii_case(settings.journal_mode.as_pragma()) {
+        log::warn!("Database uses journal mode {} instead of {}", journal_mode, settings.journal_mode.as_pragma());
+    }
+
+    connection.pragma_update(None, "synchronous", settings.synchronous.as_pragma())?;
+    connection.pragma_update(None, "foreign_keys", settings.foreign_keys)?;
+
+    Ok(())
+}
+
 pub fn setup_structure(
     pool: &Pool<SqliteConnectionManager>,
     configuration: &super::configuration::Configuration,
-) -> rusqlite::Result<()> {
-    if configuration.development_mode {
-        log::debug!("Run with --run-migrations to run migrations");
-        // @TODO: Set up with --run-migrations flag and uncomment this return.
-        // return Ok(());
-    }
-
-    log::info!("Running Migrations");
-
-    pool.get()
-        .unwrap()
-        .execute(
-            "
-            CREATE TABLE IF NOT EXISTS blocks (
-                id TEXT PRIMARY KEY,
-                parent_id TEXT NOT NULL,
-                block_type VARCHAR(255) NOT NULL,
-                data TEXT NOT NULL,
-                block_order INTEGER NOT NULL DEFAULT 0,
-                favorite BOOLEAN NOT NULL DEFAULT 0,
-                created_at_utc DATETIME NOT NULL,
-                updated_at_utc DATETIME NOT NULL
-            );",
-            [],
-        )
-        .unwrap();
-
-    // TODO: Add indexes
-    // CREATE INDEX idx_parent_id ON blocks(parent_id);
-    // CREATE INDEX idx_type ON blocks(type);
-    // CREATE INDEX idx_created_at_utc ON blocks(created_at_utc);
-    // CREATE INDEX idx_updated_at_utc ON blocks(updated_at_utc);
+) -> Result<(), String> {
+    let mut connection = pool.get().map_err(|e| {
+        log::error!("Could not get db connection: {:?}", e);
+        String::from("Could not set up database structure")
+    })?;
 
-    Ok(())
+    let version = migrations::check_version(&connection)?;
+
+    // Development databases are only migrated on request, a new database still gets its structure.
+    if configuration.development_mode && !configuration.run_migrations && version > 0 {
+        if version < migrations::LATEST_VERSION {
+            log::debug!("Run with --run-migrations to run migrations");
+        }
+        return Ok(());
+    }
+
+    migrations::run_migrations(&mut connection)
+}
+
+// The pool used by every command. `open_database_command` swaps it for a pool on another file,
+// connections handed out before keep working on the previous database until they are returned.
+// An encrypted database stays locked, without a pool, until it is unlocked with its passphrase.
+pub struct Database {
+    pool: RwLock<Option<Pool<SqliteConnectionManager>>>,
+    // Set while a command replaces or rewrites the database file, with the pool closed.
+    replacing: AtomicBool,
+}
+
+// Handed out by `Database::close`, commands are told the database is being replaced until it is dropped.
+pub struct ClosedDatabase<'a> {
+    database: &'a Database,
+}
+
+impl Drop for ClosedDatabase<'_> {
+    fn drop(&mut self) {
+        self.database.replacing.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Database {
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Database {
+            pool: RwLock::new(Some(pool)),
+            replacing: AtomicBool::new(false),
+        }
+    }
+
+    pub fn locked() -> Self {
+        Database {
+            pool: RwLock::new(None),
+            replacing: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.pool.read().unwrap().is_none()
+    }
+
+    pub fn get(&self) -> Result<PooledConnection<SqliteConnectionManager>, String> {
+        match &*self.pool.read().unwrap() {
+            Some(pool) => pool.get().map_err(|e| {
+                log::error!("Could not get db connection: {:?}", e);
+                String::from("Could not get db connection")
+            }),
+            None if self.replacing.load(Ordering::SeqCst) => Err(String::from("Database is being replaced, try again")),
+            None => Err(String::from("Database is encrypted and has not been unlocked")),
+        }
+    }
+
+    pub fn replace(&self, pool: Pool<SqliteConnectionManager>) -> Option<Pool<SqliteConnectionManager>> {
+        self.pool.write().unwrap().replace(pool)
+    }
+
+    // Takes the pool away and closes it once every connection it handed out has been returned, so
+    // nothing reads or writes the file while it is replaced. A connection still out after `timeout`
+    // puts the pool back.
+    pub fn close(&self, timeout: Duration) -> Result<ClosedDatabase<'_>, String> {
+        let pool = {
+            let mut slot = self.pool.write().unwrap();
+            let pool = slot.take().ok_or_else(|| String::from("Database is encrypted and has not been unlocked"))?;
+            self.replacing.store(true, Ordering::SeqCst);
+            pool
+        };
+        let closed = ClosedDatabase { database: self };
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let state = pool.state();
+            if state.idle_connections == state.connections {
+                break;
+            }
+            if Instant::now() >= deadline {
+                log::error!("Could not close db, {} connections still in use", state.connections - state.idle_connections);
+                self.replace(pool);
+                return Err(String::from("Database is still in use, try again"));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        drop(pool);
+
+        Ok(closed)
+    }
+}
+
+#[tauri::command]
+pub fn open_database_command(
+    path: String,
+    passphrase: Option<String>,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running open_database_command for {}", path);
+
+    // Held for the whole switch, so no other command sees the new pool with the old configuration.
+    let mut configuration = configuration.write().unwrap();
+
+    let mut opened = configuration.clone();
+    opened.db_path = PathBuf::from(&path);
+    opened.passphrase = passphrase.map(Passphrase::new);
+    let pool = setup_database(&opened)?;
+
+    let connection = pool.get().map_err(|_| String::from("Could not open database"))?;
+
+    // Keeps the active workspace when the database has it, otherwise uses its first one.
+    let workspaces = load_workspaces(configuration.workspace_id, &connection)?;
+    opened.workspace_id = match workspaces.iter().find(|workspace| workspace.active).or(workspaces.first()) {
+        Some(workspace) => workspace.id,
+        None => Block::find_or_create_workspace_block(Uuid::now_v7(), &repository::for_connection(&connection))?.id,
+    };
+    drop(connection);
+
+    opened.db_path = std::fs::canonicalize(&opened.db_path).unwrap_or(opened.db_path);
+    opened.remember_database(configuration.db_path.clone());
+    opened.remember_database(opened.db_path.clone());
+    opened.save()?;
+
+    db.replace(pool);
+    *configuration = opened;
+    log::info!("Opened database {:?}", &configuration.db_path);
+
+    emit_app_event(&app, DATABASE_OPENED_EVENT, configuration.db_path.clone());
+
+    Ok(serde_json::to_string(&*configuration).unwrap())
 }
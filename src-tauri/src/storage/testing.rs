@@ -0,0 +1,26 @@
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+
+use super::setup_database;
+use crate::configuration::Configuration;
+
+// An in-memory database set up like the app sets up a new one, connections get the same pragmas.
+pub fn in_memory_pool() -> Pool<SqliteConnectionManager> {
+    // Every in-memory connection is a database of its own, so the pool holds a single one. In-memory
+    // databases only journal in memory.
+    let configuration: Configuration = toml::from_str(
+        r#"
+        developmentMode = false
+        configurationPath = ""
+        dbPath = ":memory:"
+        workspaceId = "00000000-0000-0000-0000-000000000000"
+
+        [database]
+        poolSize = 1
+        journalMode = "MEMORY"
+        "#,
+    )
+    .unwrap();
+
+    setup_database(&configuration).unwrap()
+}
@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use super::testing::in_memory_pool;
+use super::Database;
+
+#[test]
+fn close_waits_for_connections_in_use() {
+    let db = Database::new(in_memory_pool());
+    let connection = db.get().unwrap();
+
+    assert_eq!(db.close(Duration::from_millis(50)).err(), Some(String::from("Database is still in use, try again")));
+    // The pool is back, the connection still works and others are handed out once it is returned.
+    connection.execute_batch("SELECT 1").unwrap();
+    drop(connection);
+    assert!(db.get().is_ok());
+}
+
+#[test]
+fn commands_are_told_the_database_is_being_replaced() {
+    let db = Database::new(in_memory_pool());
+
+    let closed = db.close(Duration::from_millis(50)).unwrap();
+    assert_eq!(db.get().err(), Some(String::from("Database is being replaced, try again")));
+
+    db.replace(in_memory_pool());
+    drop(closed);
+    assert!(db.get().is_ok());
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State, WebviewUrl, WebviewWindow, WebviewWindowBuilder, WindowEvent};
+use uuid::Uuid;
+
+use crate::blocks::Block;
+use crate::configuration::Configuration;
+use crate::storage::Database;
+
+const DEFAULT_WIDTH: f64 = 800.0;
+const DEFAULT_HEIGHT: f64 = 600.0;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct WindowState {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct WindowStates {
+    #[serde(default)]
+    windows: HashMap<String, WindowState>,
+}
+
+// Sizes and positions of page windows, keyed by window label and stored next to the configuration file.
+#[derive(Debug)]
+pub struct WindowStateStore {
+    path: PathBuf,
+    states: Mutex<WindowStates>,
+}
+
+impl WindowStateStore {
+    pub fn load(configuration: &Configuration) -> Self {
+        let path = configuration.config_path.with_file_name("windows.toml");
+        log::debug!("Loading window states from {:?}", &path);
+
+        let states = match std::fs::read_to_string(&path) {
+            Ok(states_str) => toml::from_str(&states_str).unwrap_or_else(|e| {
+                log::error!("Could not parse window states, starting fresh: {:?}", e);
+                WindowStates::default()
+            }),
+            Err(_e) => WindowStates::default(),
+        };
+
+        WindowStateStore {
+            path,
+            states: Mutex::new(states),
+        }
+    }
+
+    fn get(&self, label: &str) -> Option<WindowState> {
+        self.states.lock().unwrap().windows.get(label).copied()
+    }
+
+    fn update(&self, label: &str, state: WindowState) {
+        self.states.lock().unwrap().windows.insert(label.to_string(), state);
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let states_str = toml::to_string(&*self.states.lock().unwrap()).expect("Could not serialize window states");
+
+        match std::fs::write(&self.path, states_str) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                log::error!("Could not write window states file: {:?}", e);
+                Err(String::from("Could not write window states file"))
+            }
+        }
+    }
+}
+
+// Must stay covered by the `page-*` pattern in capabilities/default.json.
+fn page_window_label(page_id: Uuid) -> String {
+    format!("page-{}", page_id)
+}
+
+fn current_window_state(window: &WebviewWindow) -> Option<WindowState> {
+    let scale_factor = window.scale_factor().ok()?;
+    let position = window.outer_position().ok()?.to_logical::<f64>(scale_factor);
+    let size = window.inner_size().ok()?.to_logical::<f64>(scale_factor);
+
+    Some(WindowState {
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+    })
+}
+
+fn track_window_state(window: &WebviewWindow, app: &AppHandle) {
+    let tracked_window = window.clone();
+    let app = app.clone();
+
+    window.on_window_event(move |event| {
+        let store = app.state::<WindowStateStore>();
+
+        match event {
+            WindowEvent::Moved(_) | WindowEvent::Resized(_) => {
+                if let Some(state) = current_window_state(&tracked_window) {
+                    store.update(tracked_window.label(), state);
+                }
+            }
+            WindowEvent::CloseRequested { .. } | WindowEvent::Destroyed => {
+                log::debug!("Saving window state for {}", tracked_window.label());
+                let _ = store.save();
+            }
+            _ => {}
+        }
+    });
+}
+
+#[tauri::command]
+pub fn open_page_window_command(
+    page_id: String,
+    db: State<Database>,
+    window_states: State<WindowStateStore>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running open_page_window_command for page {}", page_id);
+    let connection = db.get()?;
+
+    let page_uuid = Uuid::parse_str(&page_id).map_err(|_| String::from("Invalid page id"))?;
+    let page = match Block::load_by_id(page_uuid, &connection).map_err(|_| String::from("Could not load page"))? {
+        Some(page) => page,
+        None => return Err(String::from("Page not found")),
+    };
+
+    let label = page_window_label(page.id);
+
+    if let Some(window) = app.get_webview_window(&label) {
+        log::debug!("Window {} already open, focusing it", label);
+        window.set_focus().map_err(|_| String::from("Could not focus window"))?;
+        return Ok(label);
+    }
+
+    let title = page.block_contents.contents.clone().unwrap_or_default();
+    let url = WebviewUrl::App(PathBuf::from(format!("page/{}", page.id)));
+
+    let builder = WebviewWindowBuilder::new(&app, &label, url).title(title);
+    let builder = match window_states.get(&label) {
+        Some(state) => builder.inner_size(state.width, state.height).position(state.x, state.y),
+        None => builder.inner_size(DEFAULT_WIDTH, DEFAULT_HEIGHT),
+    };
+
+    let window = match builder.build() {
+        Ok(window) => window,
+        Err(e) => {
+            log::error!("Could not open window for page {}: {:?}", page.id, e);
+            return Err(String::from("Could not open page window"));
+        }
+    };
+
+    track_window_state(&window, &app);
+
+    Ok(label)
+}
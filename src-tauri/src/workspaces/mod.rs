@@ -0,0 +1,195 @@
+use std::sync::RwLock;
+
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+use crate::blocks::repository::{self, BlockRepository};
+use crate::blocks::{malformed_column, Block, BlockContent};
+use crate::configuration::Configuration;
+use crate::events::{emit_app_event, emit_block_event, BlockEvent, WORKSPACE_SWITCHED_EVENT};
+use crate::storage::Database;
+
+const DEFAULT_WORKSPACE_NAME: &str = "Workspace";
+
+// A workspace block keeps its name as the content title and the id of its home page as contents.
+#[derive(Debug, Clone, Serialize)]
+pub struct Workspace {
+    pub id: Uuid,
+    pub name: String,
+    pub home_page_id: Option<Uuid>,
+    pub active: bool,
+}
+
+impl Workspace {
+    fn from_row(row: &rusqlite::Row, active_workspace_id: Uuid) -> rusqlite::Result<Self> {
+        let id: String = row.get("id")?;
+        let data: String = row.get("data")?;
+
+        let id = Uuid::parse_str(&id).map_err(|e| malformed_column(row, "id", e))?;
+        let content = BlockContent::from_json_string(&data).map_err(|e| malformed_column(row, "data", e))?;
+
+        Ok(Workspace {
+            id,
+            name: content.title.unwrap_or_else(|| String::from(DEFAULT_WORKSPACE_NAME)),
+            home_page_id: content.contents.as_deref().and_then(|home_page_id| Uuid::parse_str(home_page_id).ok()),
+            active: id == active_workspace_id,
+        })
+    }
+}
+
+// Only reads the workspace rows, loading them as blocks would load every page below them.
+pub fn load_workspaces(active_workspace_id: Uuid, connection: &Connection) -> Result<Vec<Workspace>, String> {
+    let mut statement = connection
+        .prepare("SELECT id, data FROM blocks WHERE block_type = 'workspace' ORDER BY created_at_utc ASC")
+        .map_err(|e| {
+            log::error!("Could not prepare workspaces query: {:?}", e);
+            String::from("Could not load workspaces")
+        })?;
+    let rows = statement
+        .query_map([], |row| Workspace::from_row(row, active_workspace_id))
+        .map_err(|e| {
+            log::error!("Could not query workspaces: {:?}", e);
+            String::from("Could not load workspaces")
+        })?;
+
+    Ok(rows
+        .filter_map(|row| match row {
+            Ok(workspace) => Some(workspace),
+            Err(e) => {
+                log::error!("Skipping malformed workspace: {:?}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+fn find_workspace(workspace_id: Uuid, active_workspace_id: Uuid, connection: &Connection) -> Result<Option<Workspace>, String> {
+    Ok(load_workspaces(active_workspace_id, connection)?
+        .into_iter()
+        .find(|workspace| workspace.id == workspace_id))
+}
+
+// Makes the workspace the active one and saves the configuration, which is left as it was when saving fails.
+pub fn activate_workspace(workspace_id: Uuid, configuration: &mut Configuration, connection: &Connection) -> Result<Workspace, String> {
+    let workspace = find_workspace(workspace_id, workspace_id, connection)?
+        .ok_or_else(|| String::from("Workspace not found"))?;
+
+    let previous_workspace_id = configuration.workspace_id;
+    configuration.workspace_id = workspace.id;
+    if let Err(e) = configuration.save() {
+        configuration.workspace_id = previous_workspace_id;
+        return Err(e);
+    }
+
+    Ok(workspace)
+}
+
+fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(String::from("Workspace name cannot be empty"));
+    }
+
+    Ok(name.to_string())
+}
+
+#[tauri::command]
+pub fn list_workspaces_command(
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+) -> Result<String, String> {
+    log::debug!("Running list_workspaces_command");
+    let connection = db.get()?;
+
+    let workspaces = load_workspaces(configuration.read().unwrap().workspace_id, &connection)?;
+
+    Ok(serde_json::to_string(&workspaces).unwrap())
+}
+
+#[tauri::command]
+pub fn create_workspace_command(
+    name: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running create_workspace_command for {}", name);
+    let name = validate_name(&name)?;
+
+    let mut connection = db.get()?;
+    let transaction = connection.transaction().map_err(|_| String::from("Could not start transaction"))?;
+
+    let repository = repository::for_connection(&transaction);
+
+    // Creates the workspace together with its home page.
+    let mut workspace_block = Block::find_or_create_workspace_block(Uuid::now_v7(), &repository)?;
+    workspace_block.block_contents.title = Some(name);
+    repository.update(&workspace_block)?;
+    drop(repository);
+
+    if let Err(e) = transaction.commit() {
+        log::error!("Could not commit workspace creation: {:?}", e);
+        return Err(String::from("Could not create workspace"));
+    }
+
+    emit_block_event(&app, BlockEvent::Created, vec![workspace_block.id], vec![workspace_block.parent_id]);
+
+    let workspace = find_workspace(workspace_block.id, configuration.read().unwrap().workspace_id, &connection)?
+        .ok_or_else(|| String::from("Could not create workspace"))?;
+
+    Ok(serde_json::to_string(&workspace).unwrap())
+}
+
+#[tauri::command]
+pub fn rename_workspace_command(
+    workspace_id: String,
+    name: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running rename_workspace_command for {}", workspace_id);
+    let name = validate_name(&name)?;
+    let connection = db.get()?;
+
+    let repository = repository::for_connection(&connection);
+
+    let workspace_uuid = Uuid::parse_str(&workspace_id).map_err(|_| String::from("Invalid workspace id"))?;
+    // Only the workspace row, its pages are not needed to change the title.
+    let mut workspace_block = match repository.get_without_children(workspace_uuid)? {
+        Some(block) if block.block_type == "workspace" => block,
+        _ => return Err(String::from("Workspace not found")),
+    };
+
+    workspace_block.block_contents.title = Some(name);
+    repository.update_contents(&workspace_block)?;
+
+    emit_block_event(&app, BlockEvent::Updated, vec![workspace_block.id], vec![workspace_block.parent_id]);
+
+    let workspace = find_workspace(workspace_uuid, configuration.read().unwrap().workspace_id, &connection)?
+        .ok_or_else(|| String::from("Workspace not found"))?;
+
+    Ok(serde_json::to_string(&workspace).unwrap())
+}
+
+// Makes another workspace the active one, persisting the choice in the configuration file. Windows
+// reload their home page when they receive the `workspace-switched` event.
+#[tauri::command]
+pub fn switch_workspace_command(
+    workspace_id: String,
+    db: State<Database>,
+    configuration: State<RwLock<Configuration>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    log::debug!("Running switch_workspace_command for {}", workspace_id);
+    let connection = db.get()?;
+
+    let workspace_uuid = Uuid::parse_str(&workspace_id).map_err(|_| String::from("Invalid workspace id"))?;
+    let workspace = activate_workspace(workspace_uuid, &mut configuration.write().unwrap(), &connection)?;
+
+    emit_app_event(&app, WORKSPACE_SWITCHED_EVENT, workspace.clone());
+
+    Ok(serde_json::to_string(&workspace).unwrap())
+}
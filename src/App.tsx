@@ -1,18 +1,41 @@
 import "./index.css";
+import { useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import {
   useQuery,
 } from "@tanstack/react-query";
 import Page from "./Page";
 import { Link } from "@tanstack/react-router";
+import WorkspaceSwitcher from "./components/WorkspaceSwitcher";
+import Preferences, { applyPreferences } from "./components/Preferences";
+import ConfigurationError from "./components/ConfigurationError";
+import Backups from "./components/Backups";
+import Encryption from "./components/Encryption";
+import Unlock from "./components/Unlock";
 
 interface AppProps {
   block_id?: string;
+  showPreferences?: boolean;
 }
 
-const App: React.FC<AppProps> = ({ block_id }) => {
+const App: React.FC<AppProps> = ({ block_id, showPreferences }) => {
+
+  const databaseStatus = useQuery({
+    queryKey: ["databaseStatus"],
+    queryFn: async () => {
+      const response: any = await invoke("database_status_command");
+
+      if (response.ok === false) {
+        throw new Error(response.error)
+      }
+
+      return JSON.parse(response as string);
+    },
+  })
 
   const configuration = useQuery({
+    // Blocks can not be read until an encrypted database is unlocked.
+    enabled: databaseStatus.data?.locked === false,
     queryKey: ["loadConfiguration"],
     queryFn: async () => {
       const response: any = await invoke("load_configuration_command");
@@ -42,6 +65,14 @@ const App: React.FC<AppProps> = ({ block_id }) => {
     },
   })
 
+  useEffect(() => {
+    applyPreferences(configuration.data?.configuration.preferences)
+  }, [configuration.data])
+
+  if (databaseStatus.data?.locked) {
+    return <Unlock />
+  }
+
   if (configuration.isLoading) {
     return <></>
   }
@@ -50,9 +81,23 @@ const App: React.FC<AppProps> = ({ block_id }) => {
     <div className="">
       {configuration.data &&
         <div className="flex flex-col min-h-screen max-h-screen">
-          <Page id={block_id ?? configuration.data.workspace.block_contents.contents} />
+          <ConfigurationError />
+          {showPreferences
+            ? <>
+              <Preferences preferences={configuration.data.configuration.preferences} />
+              <Backups />
+              <Encryption encrypted={databaseStatus.data?.encrypted} />
+            </>
+            : <Page id={block_id ?? configuration.data.configuration.preferences.landingPageId ?? configuration.data.workspace.block_contents.contents} />
+          }
           {/* <div className="flex-grow" /> */}
-          <div className="underline text-right p-4"><Link to='/'>Home</Link></div>
+          <div className="flex justify-between p-4">
+            <WorkspaceSwitcher />
+            <div className="flex gap-4">
+              <div className="underline"><Link to='/preferences'>Preferences</Link></div>
+              <div className="underline"><Link to='/'>Home</Link></div>
+            </div>
+          </div>
         </div>
       }
     </div>
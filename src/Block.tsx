@@ -1,5 +1,8 @@
 import React from 'react';
 import { Link } from '@tanstack/react-router';
+import { convertFileSrc, invoke } from "@tauri-apps/api/core";
+import RichText from './components/RichText';
+import BlockList from './BlockList';
 
 interface BlockProps {
     block: any
@@ -7,14 +10,77 @@ interface BlockProps {
     raw_data: string
 }
 
+// Stored attachments are referenced as `attachment:<name>`, the app serves them on its attachment protocol.
+const imageSource = (url: string) =>
+    url.startsWith('attachment:') ? convertFileSrc(url.slice('attachment:'.length), 'attachment') : url;
+
+const TextBlockContents: React.FC<{ contents: any }> = ({ contents }) => {
+    const text = contents.rich_text
+        ? <RichText spans={contents.rich_text} />
+        : contents.contents;
+    const properties = contents.properties ?? {};
+
+    switch (contents.content_type) {
+        case 'heading':
+            return <p className={properties.level <= 2 ? 'text-xl font-semibold' : 'text-lg font-semibold'}>{text}</p>
+        case 'todo':
+            return <p><input type="checkbox" className="mr-2" checked={properties.checked ?? false} readOnly />{text}</p>
+        case 'quote':
+            return <blockquote className="border-l-2 pl-2 italic">{text}</blockquote>
+        case 'code':
+            return <pre className="font-mono text-sm">{contents.contents}</pre>
+        case 'image':
+            return <img src={imageSource(contents.contents)} alt={properties.alt} title={properties.title} />
+        case 'table':
+            return (
+                <div>
+                    <p className="font-semibold">{contents.contents}</p>
+                    <table className="text-sm border-collapse">
+                        <thead>
+                            <tr>
+                                {(properties.columns ?? []).map((column: string, index: number) =>
+                                    <th key={index} className="border px-2 text-left">{column}</th>
+                                )}
+                            </tr>
+                        </thead>
+                        <tbody>
+                            {(properties.rows ?? []).map((row: string[], rowIndex: number) =>
+                                <tr key={rowIndex}>
+                                    {row.map((cell, index) => <td key={index} className="border px-2">{cell}</td>)}
+                                </tr>
+                            )}
+                        </tbody>
+                    </table>
+                </div>
+            )
+        default:
+            return <p>{text}</p>
+    }
+}
+
 const Block: React.FC<BlockProps> = ({ block }) => {
     return (
         <div id={block.id} className="flex group/block-content">
             {block.block_type === 'page' &&
-                <Link className="underline" to={`/page/${block.id}`}>{block.block_contents.contents}</Link>
+                <>
+                    <Link className="underline" to={`/page/${block.id}`}>{block.block_contents.contents}</Link>
+                    <button
+                        className="hidden group-hover/block-content:block ml-2 text-xs text-muted-foreground"
+                        onClick={() => invoke("open_page_window_command", { pageId: block.id })}
+                    >
+                        Open in new window
+                    </button>
+                </>
             }
             {block.block_type === 'text' &&
-                <p>{block.block_contents.contents}</p>
+                <div className="flex flex-col">
+                    <TextBlockContents contents={block.block_contents} />
+                    {block.children?.length > 0 &&
+                        <div className="pl-4">
+                            <BlockList blocks={block.children} />
+                        </div>
+                    }
+                </div>
             }
         </div >
     )
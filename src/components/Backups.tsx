@@ -0,0 +1,46 @@
+import React from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { useQuery } from "@tanstack/react-query";
+
+async function fetchBackups() {
+    const response: any = await invoke("list_backups_command");
+
+    if (response.ok === false) {
+        throw new Error(response.error)
+    }
+
+    return JSON.parse(response as string);
+}
+
+// Lists database snapshots, newest first. Restoring one broadcasts `backup-restored`, which reloads every window.
+const Backups: React.FC = () => {
+    const backups = useQuery({
+        queryKey: ["backups"],
+        queryFn: fetchBackups,
+    })
+
+    if (!backups.data) {
+        return <></>
+    }
+
+    const restore = async (fileName: string) => {
+        if (window.confirm(`Restore ${fileName}? The current database is backed up first.`)) {
+            await invoke("restore_backup_command", { fileName })
+        }
+    }
+
+    return (
+        <div className="flex flex-col gap-2 p-4 max-w-md">
+            <p className="text-2xl font-semibold">Backups</p>
+            {backups.data.length === 0 && <p className="text-sm">No backups yet</p>}
+            {backups.data.map((backup: any) =>
+                <div key={backup.file_name} className="flex justify-between text-sm">
+                    <span>{new Date(backup.created_at_utc).toLocaleString()} ({Math.ceil(backup.size / 1024)} KB)</span>
+                    <button className="underline" onClick={() => restore(backup.file_name)}>Restore</button>
+                </div>
+            )}
+        </div>
+    )
+}
+
+export default Backups;
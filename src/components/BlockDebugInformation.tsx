@@ -1,9 +1,73 @@
-import React from "react";
+import React, { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useQuery, useQueryClient } from "@tanstack/react-query";
 
 type BlockDebugInformationProps = {
     block: any;
 };
 
+async function fetchDatabaseStats() {
+    const response: any = await invoke("database_stats_command");
+
+    if (response.ok === false) {
+        throw new Error(response.error)
+    }
+
+    return JSON.parse(response as string);
+}
+
+function formatSize(bytes: number) {
+    return `${Math.ceil(bytes / 1024)} KB`
+}
+
+// Where the space of the database file goes, and compacting it to give free pages back.
+const DatabaseStats: React.FC = () => {
+    const queryClient = useQueryClient()
+    const [progress, setProgress] = useState<any>(null)
+    const stats = useQuery({
+        queryKey: ["databaseStats"],
+        queryFn: fetchDatabaseStats,
+    })
+
+    useEffect(() => {
+        const compaction = listen<any>("database-compaction", (event) => setProgress(event.payload))
+
+        return () => {
+            compaction.then((unlisten) => unlisten())
+        }
+    }, [])
+
+    if (!stats.data) {
+        return <></>
+    }
+
+    const compact = async () => {
+        const response: any = await invoke("compact_database_command")
+        const summary = JSON.parse(response as string)
+        setProgress({ step: `done, ${formatSize(summary.size_before)} to ${formatSize(summary.size_after)}` })
+        queryClient.invalidateQueries({ queryKey: ["databaseStats"] })
+    }
+
+    const compacting = progress && progress.completed < progress.total
+
+    return (
+        <div className="mt-2">
+            <p>Database: {stats.data.db_path}</p>
+            <p>File size: {formatSize(stats.data.file_size)}, write-ahead log: {formatSize(stats.data.wal_size)}</p>
+            <p>Pages: {stats.data.page_count} of {stats.data.page_size} bytes, {stats.data.free_pages} free</p>
+            <p>Blocks: {Object.entries(stats.data.block_counts).map(([blockType, count]) => `${blockType} ${count}`).join(", ")}</p>
+            <p>Deepest nesting: {stats.data.deepest_nesting}</p>
+            <p>Largest pages:</p>
+            {stats.data.largest_pages.map((page: any) =>
+                <p key={page.id} className="pl-2">{page.title ?? page.id}: {page.blocks} blocks, {formatSize(page.bytes)}</p>
+            )}
+            <button className="underline" disabled={compacting} onClick={compact}>Compact database</button>
+            {progress && <span> {progress.step}{compacting && ` (${progress.completed + 1}/${progress.total})`}</span>}
+        </div>
+    )
+}
+
 const BlockDebugInformation: React.FC<BlockDebugInformationProps> = ({ block }) => {
 
     return (
@@ -12,8 +76,9 @@ const BlockDebugInformation: React.FC<BlockDebugInformationProps> = ({ block })
             <p>Block Type: {block.block_type}</p>
             <p>Block contents: {JSON.stringify(block.block_contents)}</p>
             <p>Parent ID: {block.parent_id}</p>
+            <DatabaseStats />
         </div>
     )
 }
 
-export default BlockDebugInformation;
\ No newline at end of file
+export default BlockDebugInformation;
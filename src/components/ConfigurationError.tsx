@@ -0,0 +1,29 @@
+import React, { useEffect, useState } from "react";
+import { listen } from "@tauri-apps/api/event";
+
+// Shows why a hand edit of the configuration file was not applied, until a valid one is loaded.
+const ConfigurationError: React.FC = () => {
+    const [error, setError] = useState<string | null>(null)
+
+    useEffect(() => {
+        const invalid = listen<string>("configuration-invalid", (event) => setError(event.payload))
+        const changed = listen("configuration-changed", () => setError(null))
+
+        return () => {
+            invalid.then((unlisten) => unlisten())
+            changed.then((unlisten) => unlisten())
+        }
+    }, [])
+
+    if (!error) {
+        return <></>
+    }
+
+    return (
+        <div className="px-4 py-2 text-sm text-destructive border-b">
+            The configuration file was not reloaded: {error}
+        </div>
+    )
+}
+
+export default ConfigurationError;
@@ -0,0 +1,58 @@
+import React, { useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { Button } from "./ui/button";
+import { Input } from "./ui/input";
+
+type EncryptionProps = {
+    encrypted: boolean;
+};
+
+// Encrypts a plaintext database once, or changes the passphrase of an encrypted one.
+const Encryption: React.FC<EncryptionProps> = ({ encrypted }) => {
+    const [currentPassphrase, setCurrentPassphrase] = useState("")
+    const [passphrase, setPassphrase] = useState("")
+    const [confirmation, setConfirmation] = useState("")
+    const [message, setMessage] = useState<string | null>(null)
+
+    const submit = async () => {
+        if (passphrase !== confirmation) {
+            setMessage("Passphrases do not match")
+            return
+        }
+
+        try {
+            if (encrypted) {
+                await invoke("change_passphrase_command", { currentPassphrase, newPassphrase: passphrase })
+                setMessage("Passphrase changed. Backups taken before keep the previous passphrase.")
+            } else {
+                const response: any = await invoke("encrypt_database_command", { passphrase })
+                const summary = JSON.parse(response as string)
+                setMessage(summary.unencrypted_backups > 0
+                    ? `Database encrypted. ${summary.unencrypted_backups} backups taken before are not encrypted, delete them from the backups folder once you no longer need them.`
+                    : "Database encrypted.")
+            }
+            setCurrentPassphrase("")
+            setPassphrase("")
+            setConfirmation("")
+        } catch (e) {
+            setMessage(String(e))
+        }
+    }
+
+    return (
+        <div className="flex flex-col gap-4 p-4 max-w-md">
+            <p className="text-2xl font-semibold">Encryption</p>
+            {encrypted &&
+                <Input type="password" placeholder="Current passphrase" value={currentPassphrase} onChange={(event) => setCurrentPassphrase(event.target.value)} />
+            }
+            <Input type="password" placeholder={encrypted ? "New passphrase" : "Passphrase"} value={passphrase} onChange={(event) => setPassphrase(event.target.value)} />
+            <Input type="password" placeholder="Repeat passphrase" value={confirmation} onChange={(event) => setConfirmation(event.target.value)} />
+            {message && <p className="text-sm">{message}</p>}
+            <div>
+                <Button onClick={submit}>{encrypted ? "Change passphrase" : "Encrypt database"}</Button>
+            </div>
+        </div>
+    )
+}
+
+export default Encryption;
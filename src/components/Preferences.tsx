@@ -0,0 +1,91 @@
+import React, { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { Link } from "@tanstack/react-router";
+import { Button } from "./ui/button";
+import { Input } from "./ui/input";
+
+type PreferencesProps = {
+    preferences: any;
+};
+
+// Applies the theme and editor font to the document, called whenever the configuration is loaded.
+export function applyPreferences(preferences: any) {
+    if (!preferences) {
+        return
+    }
+
+    const prefersDark = window.matchMedia("(prefers-color-scheme: dark)").matches
+    const dark = preferences.theme === "dark" || (preferences.theme === "system" && prefersDark)
+
+    document.documentElement.classList.toggle("dark", dark)
+    document.documentElement.style.fontFamily = preferences.editorFontFamily
+    document.documentElement.style.fontSize = `${preferences.editorFontSize}px`
+}
+
+// Edits the preferences section of the configuration. Saving broadcasts `configuration-changed` to every window.
+const Preferences: React.FC<PreferencesProps> = ({ preferences }) => {
+    const [draft, setDraft] = useState(preferences)
+    const [error, setError] = useState<string | null>(null)
+
+    useEffect(() => setDraft(preferences), [preferences])
+
+    const update = (key: string, value: any) => setDraft({ ...draft, [key]: value })
+
+    const save = async () => {
+        try {
+            await invoke("update_configuration_command", { preferences: draft })
+            setError(null)
+        } catch (e) {
+            setError(String(e))
+        }
+    }
+
+    return (
+        <div className="flex flex-col gap-4 p-4 max-w-md">
+            <p className="text-2xl font-semibold">Preferences</p>
+            <label className="flex flex-col gap-1 text-sm">
+                Theme
+                <select className="bg-transparent" value={draft.theme} onChange={(event) => update("theme", event.target.value)}>
+                    <option value="system">System</option>
+                    <option value="light">Light</option>
+                    <option value="dark">Dark</option>
+                </select>
+            </label>
+            <label className="flex flex-col gap-1 text-sm">
+                Landing page ID, empty for the workspace home page
+                <Input value={draft.landingPageId ?? ""} onChange={(event) => update("landingPageId", event.target.value || null)} />
+            </label>
+            <label className="flex flex-col gap-1 text-sm">
+                Date format
+                <Input value={draft.dateFormat} onChange={(event) => update("dateFormat", event.target.value)} />
+            </label>
+            <label className="flex flex-col gap-1 text-sm">
+                Week starts on
+                <select className="bg-transparent" value={draft.weekStart} onChange={(event) => update("weekStart", event.target.value)}>
+                    <option value="monday">Monday</option>
+                    <option value="saturday">Saturday</option>
+                    <option value="sunday">Sunday</option>
+                </select>
+            </label>
+            <label className="flex flex-col gap-1 text-sm">
+                Editor font
+                <Input value={draft.editorFontFamily} onChange={(event) => update("editorFontFamily", event.target.value)} />
+            </label>
+            <label className="flex flex-col gap-1 text-sm">
+                Editor font size
+                <Input type="number" value={draft.editorFontSize} onChange={(event) => update("editorFontSize", Number(event.target.value))} />
+            </label>
+            <label className="flex flex-col gap-1 text-sm">
+                Days to keep deleted blocks, 0 keeps them forever
+                <Input type="number" value={draft.trashRetentionDays} onChange={(event) => update("trashRetentionDays", Number(event.target.value))} />
+            </label>
+            {error && <p className="text-sm text-destructive">{error}</p>}
+            <div className="flex gap-4 items-center">
+                <Button onClick={save}>Save</Button>
+                <Link className="underline" to="/">Back</Link>
+            </div>
+        </div>
+    )
+}
+
+export default Preferences;
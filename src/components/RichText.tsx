@@ -0,0 +1,49 @@
+import React from "react";
+import { Link } from "@tanstack/react-router";
+
+type Span = {
+    text: string;
+    bold?: boolean;
+    italic?: boolean;
+    code?: boolean;
+    strikethrough?: boolean;
+    link?: string;
+    page_reference?: string;
+};
+
+type RichTextProps = {
+    spans: Span[];
+};
+
+const RichText: React.FC<RichTextProps> = ({ spans }) => {
+
+    return (
+        <>
+            {spans.map((span, index) => {
+                let content: React.ReactNode = span.text;
+
+                if (span.code) {
+                    content = <code className="font-mono">{content}</code>;
+                }
+                if (span.bold) {
+                    content = <strong>{content}</strong>;
+                }
+                if (span.italic) {
+                    content = <em>{content}</em>;
+                }
+                if (span.strikethrough) {
+                    content = <s>{content}</s>;
+                }
+                if (span.page_reference) {
+                    content = <Link className="underline" to={`/page/${span.page_reference}`}>{content}</Link>;
+                } else if (span.link) {
+                    content = <a className="underline" href={span.link}>{content}</a>;
+                }
+
+                return <React.Fragment key={index}>{content}</React.Fragment>;
+            })}
+        </>
+    )
+}
+
+export default RichText;
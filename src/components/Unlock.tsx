@@ -0,0 +1,31 @@
+import React, { useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { Button } from "./ui/button";
+import { Input } from "./ui/input";
+
+// Shown instead of the app while the encrypted database waits for its passphrase.
+// Unlocking broadcasts `database-opened`, which reloads every window.
+const Unlock: React.FC = () => {
+    const [passphrase, setPassphrase] = useState("")
+    const [error, setError] = useState<string | null>(null)
+
+    const unlock = async (event: React.FormEvent) => {
+        event.preventDefault()
+        try {
+            await invoke("unlock_database_command", { passphrase })
+        } catch (e) {
+            setError(String(e))
+        }
+    }
+
+    return (
+        <form className="flex flex-col gap-4 p-4 max-w-md mx-auto mt-24" onSubmit={unlock}>
+            <p className="text-2xl font-semibold">Unlock database</p>
+            <Input type="password" autoFocus placeholder="Passphrase" value={passphrase} onChange={(event) => setPassphrase(event.target.value)} />
+            {error && <p className="text-sm text-destructive">{error}</p>}
+            <Button type="submit">Unlock</Button>
+        </form>
+    )
+}
+
+export default Unlock;
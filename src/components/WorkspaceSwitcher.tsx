@@ -0,0 +1,55 @@
+import React from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { useQuery } from "@tanstack/react-query";
+
+async function fetchWorkspaces() {
+    const response: any = await invoke("list_workspaces_command");
+
+    if (response.ok === false) {
+        throw new Error(response.error)
+    }
+
+    return JSON.parse(response as string);
+}
+
+// Lists the workspaces and switches between them. The `workspace-switched` event refreshes every window.
+const WorkspaceSwitcher: React.FC = () => {
+    const workspaces = useQuery({
+        queryKey: ["workspaces"],
+        queryFn: fetchWorkspaces,
+    })
+
+    if (!workspaces.data) {
+        return <></>
+    }
+
+    const active = workspaces.data.find((workspace: any) => workspace.active)
+
+    return (
+        <div className="flex gap-2 text-sm">
+            <select
+                className="bg-transparent"
+                value={active?.id}
+                onChange={(event) => invoke("switch_workspace_command", { workspaceId: event.target.value })}
+            >
+                {workspaces.data.map((workspace: any) =>
+                    <option key={workspace.id} value={workspace.id}>{workspace.name}</option>
+                )}
+            </select>
+            <button
+                className="underline"
+                onClick={async () => {
+                    const name = window.prompt("Workspace name")
+                    if (name) {
+                        await invoke("create_workspace_command", { name })
+                        workspaces.refetch()
+                    }
+                }}
+            >
+                New workspace
+            </button>
+        </div>
+    )
+}
+
+export default WorkspaceSwitcher;
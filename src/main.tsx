@@ -7,11 +7,51 @@ import {
 } from "@tanstack/react-query"
 import { createRootRoute, createRoute, Outlet, useParams } from '@tanstack/react-router'
 import { TanStackRouterDevtools } from '@tanstack/router-devtools'
+import { listen } from '@tauri-apps/api/event'
 
 import { RouterProvider, createRouter, Link } from '@tanstack/react-router'
 
 const queryClient = new QueryClient()
 
+// Blocks commands broadcast their changes to every window, so each window refreshes
+// the blocks it displays even when the change was made somewhere else.
+const blockEvents = ["block-created", "block-updated", "block-moved", "block-deleted"]
+
+blockEvents.forEach((blockEvent) => {
+  listen(blockEvent, (event) => {
+    console.debug("Received", blockEvent, event.payload)
+    queryClient.invalidateQueries({ queryKey: ["displayedBlock"] })
+  })
+})
+
+// Switching workspaces changes the home page of every window, they all go back to it.
+listen("workspace-switched", (event) => {
+  console.debug("Received workspace-switched", event.payload)
+  queryClient.invalidateQueries({ queryKey: ["loadConfiguration"] })
+  queryClient.invalidateQueries({ queryKey: ["workspaces"] })
+  router.navigate({ to: '/' })
+})
+
+// Another database file holds different blocks and workspaces altogether, everything is reloaded.
+listen("database-opened", (event) => {
+  console.debug("Received database-opened", event.payload)
+  queryClient.invalidateQueries()
+  router.navigate({ to: '/' })
+})
+
+// Preferences changed in one window, or in the configuration file, apply to all of them.
+listen("configuration-changed", (event) => {
+  console.debug("Received configuration-changed", event.payload)
+  queryClient.invalidateQueries({ queryKey: ["loadConfiguration"] })
+})
+
+// A restored backup replaces every block, like opening another database.
+listen("backup-restored", (event) => {
+  console.debug("Received backup-restored", event.payload)
+  queryClient.invalidateQueries()
+  router.navigate({ to: '/' })
+})
+
 const rootRoute = createRootRoute({
   component: () => (
     <>
@@ -45,7 +85,17 @@ const pageRoute = createRoute({
   },
 })
 
-const routeTree = rootRoute.addChildren([indexRoute, pageRoute])
+const preferencesRoute = createRoute({
+  getParentRoute: () => rootRoute,
+  path: '/preferences',
+  component: function PreferencesRoute() {
+    return (
+      <App showPreferences />
+    )
+  },
+})
+
+const routeTree = rootRoute.addChildren([indexRoute, pageRoute, preferencesRoute])
 
 const router = createRouter({ routeTree })
 